@@ -0,0 +1,263 @@
+use byteorder::{
+    ReadBytesExt,
+    WriteBytesExt,
+};
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{
+        BufReader,
+        BufWriter,
+        Cursor,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+};
+
+use crate::{
+    external_sort::ExternalSorter,
+    Error,
+    OffsetWriter,
+    Section,
+};
+
+// Length of an entry of the adjacency section: the followed user, the number
+// of times, the offset of the page record, the total and the shortest delays
+const SF_ADJACENCY_ENTRY_LENGTH: u64 = 28;
+
+// Times a revision of a user has directly followed a revision of another user
+// on a page, and the delays between both revisions (in seconds)
+#[derive(Clone)]
+#[derive(Copy)]
+pub(crate) struct Delays {
+    pub(crate) count: u32,
+    pub(crate) shortest: u32,
+    pub(crate) total: u64,
+}
+
+impl Delays {
+    fn add(&mut self, delay: u32) {
+        self.count += 1;
+        self.shortest = self.shortest.min(delay);
+        self.total += delay as u64;
+    }
+}
+
+// Entry of a user in the adjacency section, for a page on which its revisions
+// have directly followed the revisions of another user
+pub(crate) struct Follow {
+    pub(crate) followed: u32,
+    pub(crate) page_offset: u64,
+    pub(crate) delays: Delays,
+}
+
+// Revisions directly following a revision of another user on each page, as
+// revisions of a page are in chronological order in dumps
+pub(crate) struct AdjacencyBuilder {
+    previous_revision: Option<(Vec<u8>, u32)>,
+    // delays of the current page, by (follower, followed) names
+    page_delays: HashMap<(Vec<u8>, Vec<u8>), Delays>,
+    // delays of each page, by name, until the users are numbered
+    delays: BufWriter<File>,
+}
+
+impl AdjacencyBuilder {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        Ok(AdjacencyBuilder {
+            previous_revision: None,
+            page_delays: HashMap::new(),
+            delays: BufWriter::new(tempfile::tempfile()?),
+        })
+    }
+
+    // Revisions of the same user in a row don't follow anyone, but the delay
+    // before the next user is counted from the last of them
+    pub(crate) fn revision(&mut self, user: &[u8], timestamp: u32) {
+        match &mut self.previous_revision {
+            Some((previous_user, previous_timestamp)) => {
+                if previous_user.as_slice() != user {
+                    let delay = timestamp.saturating_sub(*previous_timestamp);
+                    self.page_delays.entry((user.to_vec(), previous_user.clone()))
+                        .or_insert(Delays {
+                            count: 0,
+                            shortest: u32::MAX,
+                            total: 0,
+                        })
+                        .add(delay);
+                    previous_user.clear();
+                    previous_user.extend_from_slice(user);
+                }
+                *previous_timestamp = timestamp;
+            },
+            None => self.previous_revision = Some((user.to_vec(), timestamp)),
+        }
+    }
+
+    // Keep the delays of the page aside, unless no revision has followed
+    // another user's
+    pub(crate) fn finish_page(&mut self, page_offset: u64) -> std::io::Result<()> {
+        self.previous_revision = None;
+        if self.page_delays.is_empty() {
+            return Ok(());
+        }
+        self.delays.write_u64::<byteorder::LittleEndian>(page_offset)?;
+        self.delays.write_u32::<byteorder::LittleEndian>(self.page_delays.len() as u32)?;
+        for ((follower, followed), delays) in self.page_delays.drain() {
+            for user in [follower, followed] {
+                self.delays.write_u32::<byteorder::LittleEndian>(user.len() as u32)?;
+                self.delays.write_all(&user)?;
+            }
+            self.delays.write_u32::<byteorder::LittleEndian>(delays.count)?;
+            self.delays.write_u32::<byteorder::LittleEndian>(delays.shortest)?;
+            self.delays.write_u64::<byteorder::LittleEndian>(delays.total)?;
+        }
+        Ok(())
+    }
+
+    // Write the adjacency section: for each user in the order of the users
+    // section, the position of its first entry, then the entries of all the
+    // users, sorted by followed user then by page so that the pages on which
+    // a user has followed another one can be found by dichotomy. Entries are
+    // sorted in runs of the memory budget, keyed by their numbers and page
+    // offsets in big endian so that their bytes sort in the same order.
+    pub(crate) fn write(self, writer: &mut OffsetWriter, user_count: usize, memory_budget: Option<u64>, user_number: impl Fn(&[u8]) -> u32) -> std::io::Result<Section> {
+        let mut delays = BufReader::new(self.delays.into_inner().map_err(|error| error.into_error())?);
+        delays.seek(SeekFrom::Start(0))?;
+        let mut sorter = ExternalSorter::new(memory_budget);
+        let mut entry_counts = vec![0u64; user_count];
+        let mut user = vec![];
+        while let Ok(page_offset) = delays.read_u64::<byteorder::LittleEndian>() {
+            let entry_count = delays.read_u32::<byteorder::LittleEndian>()?;
+            for _ in 0..entry_count {
+                let mut users = [0; 2];
+                for user_number_slot in users.iter_mut() {
+                    user.resize(delays.read_u32::<byteorder::LittleEndian>()? as usize, 0);
+                    delays.read_exact(&mut user)?;
+                    *user_number_slot = user_number(&user);
+                }
+                let mut key = Vec::with_capacity(16);
+                key.write_u32::<byteorder::BigEndian>(users[0])?;
+                key.write_u32::<byteorder::BigEndian>(users[1])?;
+                key.write_u64::<byteorder::BigEndian>(page_offset)?;
+                // count, shortest and total delays
+                let mut data = vec![0; 16];
+                delays.read_exact(&mut data)?;
+                entry_counts[users[0] as usize] += 1;
+                sorter.push(key, data)?;
+            }
+        }
+        let adjacency_offset = writer.offset;
+        let mut position = 0;
+        writer.write_u64::<byteorder::LittleEndian>(position)?;
+        for entry_count in entry_counts {
+            position += entry_count;
+            writer.write_u64::<byteorder::LittleEndian>(position)?;
+        }
+        let mut follows = sorter.finish()?;
+        while let Some((key, data)) = follows.next_record()? {
+            let mut key = Cursor::new(&key[4..]);
+            let mut data = Cursor::new(data);
+            let followed = key.read_u32::<byteorder::BigEndian>()?;
+            let page_offset = key.read_u64::<byteorder::BigEndian>()?;
+            let count = data.read_u32::<byteorder::LittleEndian>()?;
+            let shortest = data.read_u32::<byteorder::LittleEndian>()?;
+            let total = data.read_u64::<byteorder::LittleEndian>()?;
+            writer.write_u32::<byteorder::LittleEndian>(followed)?;
+            writer.write_u32::<byteorder::LittleEndian>(count)?;
+            writer.write_u64::<byteorder::LittleEndian>(page_offset)?;
+            writer.write_u64::<byteorder::LittleEndian>(total)?;
+            writer.write_u32::<byteorder::LittleEndian>(shortest)?;
+        }
+        Ok(Section {
+            offset: adjacency_offset,
+            length: writer.offset - adjacency_offset,
+        })
+    }
+}
+
+// Adjacency section of an index being read
+pub(crate) struct Adjacency {
+    offset: u64,
+    user_count: u64,
+    entry_count: u64,
+}
+
+impl Adjacency {
+    pub(crate) fn read(section: Section, user_count: u64) -> Result<Self, Error> {
+        let entries_length = section.length.checked_sub(8 * (user_count + 1))
+            .filter(|entries_length| entries_length % SF_ADJACENCY_ENTRY_LENGTH == 0);
+        match entries_length {
+            Some(entries_length) => Ok(Adjacency {
+                offset: section.offset,
+                user_count,
+                entry_count: entries_length / SF_ADJACENCY_ENTRY_LENGTH,
+            }),
+            None => Err(Error::Corrupt(format!("the adjacency section is {} bytes long, which doesn't fit {} users", section.length, user_count))),
+        }
+    }
+
+    // Positions of the first and past the last entries of a user
+    fn entry_range(&self, cursor: &mut Cursor<&[u8]>, follower: u32) -> Result<(u64, u64), Error> {
+        if follower as u64 >= self.user_count {
+            return Err(Error::Corrupt(format!("user {} doesn't exist, there are only {} users", follower, self.user_count)));
+        }
+        cursor.seek(SeekFrom::Start(self.offset + 8 * follower as u64)).map_err(Error::from_index)?;
+        let start = cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        let end = cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        if start > end || end > self.entry_count {
+            return Err(Error::Corrupt(format!("entries of user {} go from {} to {}, but there are only {} entries", follower, start, end, self.entry_count)));
+        }
+        Ok((start, end))
+    }
+
+    fn entry(&self, cursor: &mut Cursor<&[u8]>, position: u64) -> Result<Follow, Error> {
+        cursor.seek(SeekFrom::Start(self.offset + 8 * (self.user_count + 1) + SF_ADJACENCY_ENTRY_LENGTH * position)).map_err(Error::from_index)?;
+        let followed = cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        let count = cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        let page_offset = cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        let total = cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        let shortest = cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        Ok(Follow {
+            followed,
+            page_offset,
+            delays: Delays {
+                count,
+                shortest,
+                total,
+            },
+        })
+    }
+
+    // All the entries of a user
+    pub(crate) fn follows(&self, cursor: &mut Cursor<&[u8]>, follower: u32) -> Result<Vec<Follow>, Error> {
+        let (start, end) = self.entry_range(cursor, follower)?;
+        (start..end).map(|position| self.entry(cursor, position)).collect()
+    }
+
+    // Entries of a user for the pages on which it has followed another one,
+    // in the order of the pages
+    pub(crate) fn follows_of(&self, cursor: &mut Cursor<&[u8]>, follower: u32, followed: u32) -> Result<Vec<Follow>, Error> {
+        let (mut low, end) = self.entry_range(cursor, follower)?;
+        let mut high = end;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.entry(cursor, middle)?.followed < followed {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        let mut follows = vec![];
+        for position in low..end {
+            let follow = self.entry(cursor, position)?;
+            if follow.followed != followed {
+                break;
+            }
+            follows.push(follow);
+        }
+        Ok(follows)
+    }
+}
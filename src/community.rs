@@ -0,0 +1,114 @@
+// Louvain method: nodes are moved to the community of a neighbour as long as
+// it increases the modularity, then communities are merged into single nodes
+// and the same is done on the resulting graph, until nothing moves anymore.
+// Weights are given as a symmetric matrix of node_count × node_count, and the
+// community of each node is returned, numbered in the order of their first
+// node. Nodes are visited in order, so that the result is deterministic.
+pub(crate) fn louvain(weights: &[f64], node_count: usize) -> Vec<usize> {
+    let mut communities: Vec<usize> = (0..node_count).collect();
+    let mut graph = weights.to_vec();
+    let mut graph_node_count = node_count;
+    while let Some(assignment) = move_nodes(&graph, graph_node_count) {
+        let assignment = renumber(&assignment);
+        let community_count = assignment.iter().max().map_or(0, |community| community + 1);
+        for community in communities.iter_mut() {
+            *community = assignment[*community];
+        }
+        // weights within a community become a self loop of its node
+        let mut community_graph = vec![0.; community_count * community_count];
+        for first_node in 0..graph_node_count {
+            for second_node in 0..graph_node_count {
+                community_graph[assignment[first_node] * community_count + assignment[second_node]] += graph[first_node * graph_node_count + second_node];
+            }
+        }
+        graph = community_graph;
+        graph_node_count = community_count;
+    }
+    renumber(&communities)
+}
+
+// Move each node to the neighbouring community which increases the most the
+// modularity, until none moves, or return None if none has moved at all
+fn move_nodes(graph: &[f64], node_count: usize) -> Option<Vec<usize>> {
+    let total_weight: f64 = graph.iter().sum();
+    if total_weight <= 0. {
+        return None;
+    }
+    let degrees: Vec<f64> = graph.chunks(node_count).map(|row| row.iter().sum()).collect();
+    let mut communities: Vec<usize> = (0..node_count).collect();
+    let mut community_degrees = degrees.clone();
+    let mut moved = false;
+    loop {
+        let mut pass_moved = false;
+        for node in 0..node_count {
+            let current_community = communities[node];
+            community_degrees[current_community] -= degrees[node];
+            let mut community_weights = vec![0.; node_count];
+            for neighbour in (0..node_count).filter(|neighbour| *neighbour != node) {
+                community_weights[communities[neighbour]] += graph[node * node_count + neighbour];
+            }
+            let gain = |community: usize| community_weights[community] - community_degrees[community] * degrees[node] / total_weight;
+            let mut best_community = current_community;
+            let mut best_gain = gain(current_community);
+            for (community, community_weight) in community_weights.iter().enumerate() {
+                if *community_weight > 0. && gain(community) > best_gain + 1e-12 {
+                    best_community = community;
+                    best_gain = gain(community);
+                }
+            }
+            community_degrees[best_community] += degrees[node];
+            if best_community != current_community {
+                communities[node] = best_community;
+                pass_moved = true;
+                moved = true;
+            }
+        }
+        if !pass_moved {
+            break;
+        }
+    }
+    if moved {
+        Some(communities)
+    } else {
+        None
+    }
+}
+
+// Number communities from 0, in the order of their first node
+fn renumber(communities: &[usize]) -> Vec<usize> {
+    let mut numbers = vec![usize::MAX; communities.len()];
+    let mut community_count = 0;
+    communities.iter()
+        .map(|community| {
+            if numbers[*community] == usize::MAX {
+                numbers[*community] = community_count;
+                community_count += 1;
+            }
+            numbers[*community]
+        })
+        .collect()
+}
+
+// Modularity of the communities of the nodes: the fraction of the weights
+// within communities, minus the fraction expected if edges were random
+pub(crate) fn modularity(weights: &[f64], node_count: usize, communities: &[usize]) -> f64 {
+    let total_weight: f64 = weights.iter().sum();
+    if total_weight <= 0. {
+        return 0.;
+    }
+    let community_count = communities.iter().max().map_or(0, |community| community + 1);
+    let mut internal_weights = vec![0.; community_count];
+    let mut community_degrees = vec![0.; community_count];
+    for first_node in 0..node_count {
+        for second_node in 0..node_count {
+            let weight = weights[first_node * node_count + second_node];
+            community_degrees[communities[first_node]] += weight;
+            if communities[first_node] == communities[second_node] {
+                internal_weights[communities[first_node]] += weight;
+            }
+        }
+    }
+    internal_weights.iter().zip(&community_degrees)
+        .map(|(internal_weight, community_degree)| internal_weight / total_weight - (community_degree / total_weight).powi(2))
+        .sum()
+}
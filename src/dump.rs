@@ -0,0 +1,191 @@
+use quick_xml::{
+    Reader,
+    events::{
+        BytesStart,
+        Event,
+    },
+};
+
+use std::io::{
+    BufRead,
+    BufReader,
+};
+
+use crate::{
+    parse_timestamp,
+    Error,
+};
+
+enum Tag {
+    DbName,
+    Base,
+    Namespace,
+    Title,
+    PageNamespace,
+    Timestamp,
+    UserName,
+    Other,
+}
+
+// Receives what socksfinder needs from a MediaWiki XML dump, in the order of the dump
+pub(crate) trait DumpHandler {
+    fn site(&mut self, dbname: &str, base_url: &str);
+    fn namespace(&mut self, key: i32, name: &str);
+    // Called once per page, before any of its revisions
+    fn page(&mut self, namespace: i32, title: &str) -> Result<(), Error>;
+    // Called for each revision of the current page, with the name (or IP) of its author
+    fn revision(&mut self, user: &str, timestamp: u32) -> Result<(), Error>;
+}
+
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const ZSTD_MAGIC: &[u8] = b"\x28\xB5\x2F\xFD";
+
+/// Wrap a reader on a MediaWiki XML dump so that it is transparently
+/// decompressed if it's compressed using gzip, bzip2 or zstd (detected from
+/// its first bytes), or returned as is otherwise.
+pub fn open_dump<'a>(mut reader: Box<dyn BufRead + 'a>) -> std::io::Result<Box<dyn BufRead + 'a>> {
+    let magic = reader.fill_buf()?;
+    Ok(if magic.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(BZIP2_MAGIC) {
+        Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else {
+        reader
+    })
+}
+
+fn namespace_key(event: &BytesStart) -> Option<i32> {
+    let key = event.try_get_attribute("key").ok()??;
+    std::str::from_utf8(&key.value).ok()?.parse().ok()
+}
+
+pub(crate) fn parse(reader: &mut dyn BufRead, handler: &mut dyn DumpHandler) -> Result<(), Error> {
+    let mut xml_reader = Reader::from_reader(reader);
+    let mut buffer = Vec::new();
+    let mut current_tag = Tag::Other;
+    let mut current_namespace_key = 0i32;
+    let mut current_namespace = 0i32;
+    let mut current_timestamp = 0u32;
+    let mut pending_title: Option<String> = None;
+    let mut dbname = String::new();
+    let mut base_url = String::new();
+    loop {
+        match xml_reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(ref event)) => {
+                match event.name().as_ref() {
+                    b"dbname" => current_tag = Tag::DbName,
+                    b"base" => current_tag = Tag::Base,
+                    b"namespace" => {
+                        current_tag = Tag::Namespace;
+                        current_namespace_key = namespace_key(event).unwrap_or(0);
+                    },
+                    b"title" => current_tag = Tag::Title,
+                    b"ns" => current_tag = Tag::PageNamespace,
+                    b"revision" => {
+                        current_tag = Tag::Other;
+                        // the page can't be handled before its namespace is known
+                        if let Some(title) = pending_title.take() {
+                            handler.page(current_namespace, &title)?;
+                        }
+                    },
+                    b"timestamp" => current_tag = Tag::Timestamp,
+                    b"ip" => current_tag = Tag::UserName,
+                    b"username" => current_tag = Tag::UserName,
+                    _ => current_tag = Tag::Other,
+                }
+            },
+            Ok(Event::Empty(ref event)) if event.name().as_ref() == b"namespace" => {
+                if let Some(key) = namespace_key(event) {
+                    handler.namespace(key, "");
+                }
+            },
+            Ok(Event::End(ref event)) => {
+                current_tag = Tag::Other;
+                match event.name().as_ref() {
+                    b"siteinfo" => handler.site(&dbname, &base_url),
+                    b"page" => {
+                        if let Some(title) = pending_title.take() {
+                            handler.page(current_namespace, &title)?;
+                        }
+                    },
+                    _ => (),
+                }
+            },
+            Ok(Event::Text(ref event)) => {
+                let escaped_event = event.unescape();
+                match current_tag {
+                    Tag::DbName => {
+                        match escaped_event {
+                            Ok(ref buffer) => dbname = buffer.to_string(),
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::Base => {
+                        match escaped_event {
+                            Ok(ref buffer) => base_url = buffer.to_string(),
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::Namespace => {
+                        match escaped_event {
+                            Ok(ref buffer) => {
+                                handler.namespace(current_namespace_key, buffer);
+                            },
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::Title => {
+                        match escaped_event {
+                            Ok(ref buffer) => {
+                                pending_title = Some(buffer.to_string());
+                                current_namespace = 0;
+                            }
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    }
+                    Tag::PageNamespace => {
+                        match escaped_event {
+                            Ok(ref buffer) => {
+                                current_namespace = buffer.parse().unwrap_or(0);
+                            },
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::Timestamp => {
+                        match escaped_event {
+                            Ok(ref buffer) => {
+                                current_timestamp = parse_timestamp(buffer).unwrap_or(0);
+                            },
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::UserName => {
+                        match escaped_event {
+                            Ok(ref buffer) => {
+                                handler.revision(&buffer.replace("_", " "), current_timestamp)?;
+                            },
+                            Err(_) => (), // ignore encoding error in the dump
+                        }
+                    },
+                    Tag::Other => (),
+                }
+            },
+            // errors of the underlying reader (eg. truncated compressed dump) aren't XML errors
+            Err(quick_xml::Error::Io(error)) => return Err(Error::Io(std::io::Error::new(error.kind(), error.to_string()))),
+            Err(error) => return Err(Error::Xml {
+                position: xml_reader.buffer_position() as u64,
+                message: error.to_string(),
+            }),
+            Ok(Event::Eof) => break,
+            _ => (),
+        }
+        buffer.clear();
+    }
+    if let Some(title) = pending_title.take() {
+        handler.page(current_namespace, &title)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use actix_web::{
+    http::StatusCode,
+    ResponseError,
+};
+
+use std::fmt;
+
+use crate::{
+    version,
+    SF_VERSION,
+    SF_VERSION_32_BITS,
+};
+
+/// Error while building, updating, checking or searching an index
+#[derive(Debug)]
+pub enum Error {
+    /// The file doesn't start with the identifier of socksfinder indexes
+    BadMagic,
+    /// The index is in a format version newer than the ones supported by
+    /// this version of socksfinder
+    UnsupportedVersion(u16),
+    /// The index is in a format version too old for what has been asked
+    /// (eg. searching by date in an index without timestamps)
+    OutdatedIndex(String),
+    /// A section of the index is corrupt or truncated
+    Corrupt(String),
+    /// Users searched for who do not exist in the index or have no edits
+    UnknownUsers(Vec<String>),
+    /// Pages searched for which do not exist in the index
+    UnknownPages(Vec<String>),
+    /// The query can't be answered as is (eg. unknown namespace)
+    InvalidQuery(String),
+    /// The dump isn't valid XML
+    Xml {
+        /// Position in the (uncompressed) dump, in bytes
+        position: u64,
+        message: String,
+    },
+    /// Error while reading or writing an index, a dump or a temporary file
+    Io(std::io::Error),
+}
+
+impl Error {
+    // Error while reading an index, which is corrupt or truncated if its data
+    // end too soon or aren't what they should be
+    pub(crate) fn from_index(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::Corrupt("unexpected end of index".to_string()),
+            std::io::ErrorKind::InvalidData => Error::Corrupt(error.to_string()),
+            _ => Error::Io(error),
+        }
+    }
+
+    /// Exit code of the command line tool for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            Error::InvalidQuery(_) => 2,
+            Error::UnknownUsers(_) |
+            Error::UnknownPages(_) => 3,
+            Error::BadMagic |
+            Error::UnsupportedVersion(_) |
+            Error::OutdatedIndex(_) => 4,
+            Error::Corrupt(_) => 5,
+            Error::Xml { .. } => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadMagic => write!(formatter, "not a socksfinder index"),
+            Error::UnsupportedVersion(index_version) => write!(formatter, "can't read index in format version {}, only format versions {} to {} are supported by socksfinder v{}", index_version, SF_VERSION_32_BITS, SF_VERSION, version()),
+            Error::OutdatedIndex(message) => write!(formatter, "{}", message),
+            Error::Corrupt(message) => write!(formatter, "corrupt or truncated index: {}", message),
+            Error::UnknownUsers(users) => {
+                let users: Vec<_> = users.iter().map(|user| format!("'{}'", user)).collect();
+                if users.len() == 1 {
+                    write!(formatter, "user {} does not exist or has no edits", users[0])
+                } else {
+                    write!(formatter, "users {} do not exist or have no edits", users.join(", "))
+                }
+            },
+            Error::UnknownPages(pages) => {
+                let pages: Vec<_> = pages.iter().map(|page| format!("'{}'", page)).collect();
+                if pages.len() == 1 {
+                    write!(formatter, "page {} does not exist", pages[0])
+                } else {
+                    write!(formatter, "pages {} do not exist", pages.join(", "))
+                }
+            },
+            Error::InvalidQuery(message) => write!(formatter, "{}", message),
+            Error::Xml { position, message } => write!(formatter, "XML parsing error at position {}: {}", position, message),
+            Error::Io(error) => write!(formatter, "I/O error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<fst::Error> for Error {
+    fn from(error: fst::Error) -> Self {
+        match error {
+            fst::Error::Io(error) => error.into(),
+            fst::Error::Fst(error) => Error::Corrupt(format!("FST: {}", error)),
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::InvalidQuery(_) |
+            Error::OutdatedIndex(_) => StatusCode::BAD_REQUEST,
+            Error::UnknownUsers(_) |
+            Error::UnknownPages(_) => StatusCode::NOT_FOUND,
+            Error::BadMagic |
+            Error::UnsupportedVersion(_) |
+            Error::Corrupt(_) |
+            Error::Xml { .. } |
+            Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashSet,
+    str::FromStr,
+};
+
+use crate::Error;
+
+// Characters which can't be part of a user name unless it's quoted
+const OPERATORS: &[char] = &['&', '|', '!', '(', ')', ',', '"'];
+
+/// Boolean combination of users, to search pages modified by some users but
+/// not by others, eg. `(Arkanosis | Arktest) & !Arkbot` or
+/// `2 of (Arkanosis, Arktest, Arkbot)`. User names containing operators
+/// must be quoted.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Expression {
+    /// Pages modified by the user
+    User(String),
+    /// Pages matching all the expressions
+    And(Vec<Expression>),
+    /// Pages matching any of the expressions
+    Or(Vec<Expression>),
+    /// Pages not matching the expression
+    Not(Box<Expression>),
+    /// Pages matching at least this number of the expressions
+    AtLeast(usize, Vec<Expression>),
+}
+
+impl Expression {
+    /// Users of the expression, without duplicates, in the order they
+    /// first appear in it
+    pub fn users(&self) -> Vec<String> {
+        let mut users = vec![];
+        self.collect_users(&mut users);
+        users
+    }
+
+    // Add the users of the expression not yet in users
+    fn collect_users(&self, users: &mut Vec<String>) {
+        match self {
+            Expression::User(user) => if !users.contains(user) {
+                users.push(user.clone());
+            },
+            Expression::And(operands) |
+            Expression::Or(operands) |
+            Expression::AtLeast(_, operands) => for operand in operands {
+                operand.collect_users(users);
+            },
+            Expression::Not(operand) => operand.collect_users(users),
+        }
+    }
+
+    /// Whether a page matches the expression, given whether each of the
+    /// users has modified it
+    pub fn evaluate(&self, is_editor: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Expression::User(user) => is_editor(user),
+            Expression::And(operands) => operands.iter().all(|operand| operand.evaluate(is_editor)),
+            Expression::Or(operands) => operands.iter().any(|operand| operand.evaluate(is_editor)),
+            Expression::Not(operand) => !operand.evaluate(is_editor),
+            Expression::AtLeast(count, operands) => operands.iter().filter(|operand| operand.evaluate(is_editor)).count() >= *count,
+        }
+    }
+
+    // Number of users who must have modified a page for it to match, used
+    // as the threshold of the search to skip the other pages early
+    pub(crate) fn required_user_count(&self) -> usize {
+        self.required_users().len()
+    }
+
+    // Users who must have modified a page for it to match
+    fn required_users(&self) -> HashSet<&str> {
+        match self {
+            Expression::User(user) => HashSet::from([user.as_str()]),
+            Expression::And(operands) => operands.iter().flat_map(|operand| operand.required_users()).collect(),
+            Expression::AtLeast(count, operands) if *count == operands.len() => operands.iter().flat_map(|operand| operand.required_users()).collect(),
+            Expression::Or(operands) |
+            Expression::AtLeast(_, operands) => {
+                let mut operands = operands.iter().map(|operand| operand.required_users());
+                let first_users = operands.next().unwrap_or_default();
+                operands.fold(first_users, |users, operand_users| &users & &operand_users)
+            },
+            Expression::Not(_) => HashSet::new(),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = Error;
+
+    /// Parse an expression where `!` binds tighter than `&`, which binds
+    /// tighter than `|`
+    fn from_str(expression: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            expression,
+            position: 0,
+        };
+        let parsed_expression = parser.parse_or()?;
+        if let Some(character) = parser.peek() {
+            return Err(parser.error(&format!("unexpected '{}'", character)));
+        }
+        // pages are found through the lists of the users, so the ones
+        // modified by none of them can't be
+        if parsed_expression.evaluate(&|_| false) {
+            return Err(Error::InvalidQuery(format!("expression '{}' matches pages modified by none of its users", expression)));
+        }
+        Ok(parsed_expression)
+    }
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    // in bytes
+    position: usize,
+}
+
+impl Parser<'_> {
+    // Next character which isn't a space, without consuming it
+    fn peek(&mut self) -> Option<char> {
+        let remaining = &self.expression[self.position..];
+        self.position += remaining.len() - remaining.trim_start().len();
+        self.expression[self.position..].chars().next()
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::InvalidQuery(format!("invalid expression '{}': {} at position {}", self.expression, message, self.position))
+    }
+
+    fn expect(&mut self, expected_character: char) -> Result<(), Error> {
+        match self.peek() {
+            Some(character) if character == expected_character => {
+                self.position += character.len_utf8();
+                Ok(())
+            },
+            Some(character) => Err(self.error(&format!("expected '{}' instead of '{}'", expected_character, character))),
+            None => Err(self.error(&format!("expected '{}'", expected_character))),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, Error> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expression::Or(operands)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, Error> {
+        let mut operands = vec![self.parse_not()?];
+        while self.peek() == Some('&') {
+            self.position += 1;
+            operands.push(self.parse_not()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expression::And(operands)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Expression, Error> {
+        if self.peek() == Some('!') {
+            self.position += 1;
+            return Ok(Expression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_operand()
+    }
+
+    fn parse_operand(&mut self) -> Result<Expression, Error> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expression = self.parse_or()?;
+                self.expect(')')?;
+                Ok(expression)
+            },
+            Some('"') => {
+                self.position += 1;
+                let length = match self.expression[self.position..].find('"') {
+                    Some(length) => length,
+                    None => return Err(self.error("unterminated quote")),
+                };
+                let user = &self.expression[self.position..self.position + length];
+                self.position += length + 1;
+                self.user(user)
+            },
+            Some(character) if !OPERATORS.contains(&character) => {
+                let remaining = &self.expression[self.position..];
+                let length = remaining.find(OPERATORS).unwrap_or(remaining.len());
+                let word = &remaining[..length];
+                self.position += length;
+                match word.split_whitespace().collect::<Vec<_>>()[..] {
+                    [count, "of"] if self.peek() == Some('(') => match count.parse() {
+                        Ok(count) => self.parse_at_least(count),
+                        Err(_) => Err(self.error(&format!("invalid count '{}'", count))),
+                    },
+                    _ => self.user(word),
+                }
+            },
+            Some(character) => Err(self.error(&format!("unexpected '{}'", character))),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    // Comma-separated expressions of "<count> of (...)", after "of"
+    fn parse_at_least(&mut self, count: usize) -> Result<Expression, Error> {
+        self.expect('(')?;
+        let mut operands = vec![self.parse_or()?];
+        while self.peek() == Some(',') {
+            self.position += 1;
+            operands.push(self.parse_or()?);
+        }
+        self.expect(')')?;
+        if count == 0 || count > operands.len() {
+            return Err(self.error(&format!("count {} out of 1 to {}", count, operands.len())));
+        }
+        Ok(Expression::AtLeast(count, operands))
+    }
+
+    // Same normalization as for the users of the other queries
+    fn user(&self, user: &str) -> Result<Expression, Error> {
+        let user = user.replace("_", " ").trim().to_string();
+        if user.is_empty() {
+            return Err(self.error("empty user name"));
+        }
+        Ok(Expression::User(user))
+    }
+}
@@ -0,0 +1,309 @@
+use byteorder::{
+    ReadBytesExt,
+    WriteBytesExt,
+};
+
+use fst::MapBuilder;
+
+use std::{
+    cmp::Reverse,
+    collections::{
+        BinaryHeap,
+        BTreeMap,
+    },
+    fs::File,
+    io::{
+        BufReader,
+        BufWriter,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+};
+
+use crate::{
+    append_posting,
+    Error,
+    Posting,
+};
+
+// Run of postings sorted by user, written to a temporary file. Each user is
+// written as its length, its name, its number of postings and then its
+// postings, in the same format as in the index.
+pub(crate) struct RunWriter {
+    run: BufWriter<File>,
+}
+
+impl RunWriter {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        Ok(RunWriter {
+            run: BufWriter::new(tempfile::tempfile()?),
+        })
+    }
+
+    // Users have to be pushed in alphabetical order
+    pub(crate) fn push(&mut self, user: &[u8], postings: &[Posting]) -> std::io::Result<()> {
+        self.run.write_u32::<byteorder::LittleEndian>(user.len() as u32)?;
+        self.run.write_all(user)?;
+        self.run.write_u64::<byteorder::LittleEndian>(postings.len() as u64)?;
+        for posting in postings {
+            self.run.write_u64::<byteorder::LittleEndian>(posting.page_offset)?;
+            self.run.write_u32::<byteorder::LittleEndian>(posting.first_edit)?;
+            self.run.write_u32::<byteorder::LittleEndian>(posting.last_edit)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> std::io::Result<File> {
+        let mut run = self.run.into_inner()?;
+        run.seek(SeekFrom::Start(0))?;
+        Ok(run)
+    }
+}
+
+// Write the postings collected so far to a run and leave the map empty
+pub(crate) fn spill(user_postings: &mut BTreeMap<Vec<u8>, Vec<Posting>>) -> std::io::Result<File> {
+    let mut run = RunWriter::new()?;
+    for (user, postings) in std::mem::take(user_postings) {
+        run.push(&user, &postings)?;
+    }
+    run.finish()
+}
+
+struct RunReader {
+    reader: BufReader<File>,
+    posting_count: u64,
+}
+
+impl RunReader {
+    fn next_user(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let user_length = match self.reader.read_u32::<byteorder::LittleEndian>() {
+            Ok(user_length) => user_length,
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let mut user = vec![0u8; user_length as usize];
+        self.reader.read_exact(&mut user)?;
+        self.posting_count = self.reader.read_u64::<byteorder::LittleEndian>()?;
+        Ok(Some(user))
+    }
+
+    fn read_postings(&mut self, postings: &mut Vec<Posting>) -> std::io::Result<()> {
+        for _ in 0..self.posting_count {
+            append_posting(postings, Posting {
+                page_offset: self.reader.read_u64::<byteorder::LittleEndian>()?,
+                first_edit: self.reader.read_u32::<byteorder::LittleEndian>()?,
+                last_edit: self.reader.read_u32::<byteorder::LittleEndian>()?,
+            });
+        }
+        Ok(())
+    }
+}
+
+// k-way merge of runs written by spill(), in the order they were written
+pub(crate) struct RunMerger {
+    runs: Vec<RunReader>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+}
+
+impl RunMerger {
+    pub(crate) fn new(runs: Vec<File>) -> std::io::Result<Self> {
+        let mut merger = RunMerger {
+            runs: runs.into_iter()
+                .map(|run| RunReader {
+                    reader: BufReader::new(run),
+                    posting_count: 0,
+                })
+                .collect(),
+            heap: BinaryHeap::new(),
+        };
+        for run_index in 0..merger.runs.len() {
+            if let Some(user) = merger.runs[run_index].next_user()? {
+                merger.heap.push(Reverse((user, run_index)));
+            }
+        }
+        Ok(merger)
+    }
+
+    // Get the next user in alphabetical order, along with all its postings.
+    // Since runs are written in the order of the dump, the postings of a
+    // user can be concatenated run after run, only a page on which a run
+    // ended and the next one started has to be merged.
+    pub(crate) fn next_user(&mut self, postings: &mut Vec<Posting>) -> std::io::Result<Option<Vec<u8>>> {
+        let user = match self.heap.peek() {
+            Some(Reverse((user, _))) => user.clone(),
+            None => return Ok(None),
+        };
+        while let Some(Reverse((next_user, _))) = self.heap.peek() {
+            if *next_user != user {
+                break;
+            }
+            let Reverse((_, run_index)) = self.heap.pop().unwrap();
+            let run = &mut self.runs[run_index];
+            run.read_postings(postings)?;
+            if let Some(next_user) = run.next_user()? {
+                self.heap.push(Reverse((next_user, run_index)));
+            }
+        }
+        Ok(Some(user))
+    }
+}
+
+// Users along with the offsets of their postings, kept in a temporary file
+// until all the postings have been written and the FST can be built
+pub(crate) struct UserOffsets {
+    users: BufWriter<File>,
+}
+
+impl UserOffsets {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        Ok(UserOffsets {
+            users: BufWriter::new(tempfile::tempfile()?),
+        })
+    }
+
+    pub(crate) fn push(&mut self, user: &[u8], postings_offset: u64) -> std::io::Result<()> {
+        self.users.write_u32::<byteorder::LittleEndian>(user.len() as u32)?;
+        self.users.write_all(user)?;
+        self.users.write_u64::<byteorder::LittleEndian>(postings_offset)
+    }
+
+    pub(crate) fn write_fst<W: Write>(self, writer: W) -> Result<(), Error> {
+        let mut users = BufReader::new(self.users.into_inner().map_err(|error| error.into_error())?);
+        users.seek(SeekFrom::Start(0))?;
+        let mut fst_builder = MapBuilder::new(writer)?;
+        let mut user = vec![];
+        while let Ok(user_length) = users.read_u32::<byteorder::LittleEndian>() {
+            user.resize(user_length as usize, 0);
+            users.read_exact(&mut user)?;
+            fst_builder.insert(&user, users.read_u64::<byteorder::LittleEndian>()?)?;
+        }
+        fst_builder.finish()?;
+        Ok(())
+    }
+}
+
+// Rough memory cost of a record kept in memory by a sorter, in addition to
+// its key and its data
+const SF_RECORD_MEMORY_OVERHEAD: u64 = 64;
+
+// Records made of a key and some data, sorted by key; they are written to
+// temporary files in sorted runs when they take more than the memory budget,
+// and records with the same key stay in the order they have been pushed
+pub(crate) struct ExternalSorter {
+    records: Vec<(Vec<u8>, Vec<u8>)>,
+    memory: u64,
+    memory_budget: Option<u64>,
+    runs: Vec<File>,
+}
+
+impl ExternalSorter {
+    pub(crate) fn new(memory_budget: Option<u64>) -> Self {
+        ExternalSorter {
+            records: vec![],
+            memory: 0,
+            memory_budget,
+            runs: vec![],
+        }
+    }
+
+    // Approximate memory taken by the records not spilled yet
+    pub(crate) fn memory(&self) -> u64 {
+        self.memory
+    }
+
+    pub(crate) fn push(&mut self, key: Vec<u8>, data: Vec<u8>) -> std::io::Result<()> {
+        self.memory += key.len() as u64 + data.len() as u64 + SF_RECORD_MEMORY_OVERHEAD;
+        self.records.push((key, data));
+        if self.memory_budget.is_some_and(|memory_budget| self.memory > memory_budget) {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    // Write the records kept in memory to a run, sorted by key, each of them
+    // as the length of its key, its key, the length of its data and its data
+    pub(crate) fn spill(&mut self) -> std::io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        self.records.sort_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key));
+        let mut run = BufWriter::new(tempfile::tempfile()?);
+        for (key, data) in self.records.drain(..) {
+            run.write_u32::<byteorder::LittleEndian>(key.len() as u32)?;
+            run.write_all(&key)?;
+            run.write_u32::<byteorder::LittleEndian>(data.len() as u32)?;
+            run.write_all(&data)?;
+        }
+        let mut run = run.into_inner()?;
+        run.seek(SeekFrom::Start(0))?;
+        self.runs.push(run);
+        self.memory = 0;
+        Ok(())
+    }
+
+    // Sort the records, merging the runs with the records still in memory
+    pub(crate) fn finish(mut self) -> std::io::Result<SortedRecords> {
+        if self.runs.is_empty() {
+            self.records.sort_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key));
+            return Ok(SortedRecords {
+                records: self.records.into_iter(),
+                runs: vec![],
+                heap: BinaryHeap::new(),
+            });
+        }
+        self.spill()?;
+        let mut sorted_records = SortedRecords {
+            records: vec![].into_iter(),
+            runs: self.runs.into_iter().map(BufReader::new).collect(),
+            heap: BinaryHeap::new(),
+        };
+        for run_index in 0..sorted_records.runs.len() {
+            if let Some((key, data)) = read_record(&mut sorted_records.runs[run_index])? {
+                sorted_records.heap.push(Reverse((key, run_index, data)));
+            }
+        }
+        Ok(sorted_records)
+    }
+}
+
+fn read_record(run: &mut BufReader<File>) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let key_length = match run.read_u32::<byteorder::LittleEndian>() {
+        Ok(key_length) => key_length,
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let mut key = vec![0u8; key_length as usize];
+    run.read_exact(&mut key)?;
+    let mut data = vec![0u8; run.read_u32::<byteorder::LittleEndian>()? as usize];
+    run.read_exact(&mut data)?;
+    Ok(Some((key, data)))
+}
+
+// Key of a record, the run it comes from and its data
+type RunRecord = (Vec<u8>, usize, Vec<u8>);
+
+// Records of a sorter, either sorted in memory or k-way merged from its runs;
+// runs written first come first for records with the same key
+pub(crate) struct SortedRecords {
+    records: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    runs: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<RunRecord>>,
+}
+
+impl SortedRecords {
+    pub(crate) fn next_record(&mut self) -> std::io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.runs.is_empty() {
+            return Ok(self.records.next());
+        }
+        let Reverse((key, run_index, data)) = match self.heap.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Some((next_key, next_data)) = read_record(&mut self.runs[run_index])? {
+            self.heap.push(Reverse((next_key, run_index, next_data)));
+        }
+        Ok(Some((key, data)))
+    }
+}
@@ -1,3 +1,14 @@
+mod adjacency;
+mod community;
+mod dump;
+mod error;
+mod expression;
+mod external_sort;
+mod output;
+mod reader;
+mod signatures;
+mod verify;
+
 use actix_files::NamedFile;
 
 use actix_web::{
@@ -12,6 +23,7 @@ use actix_web::{
     HttpResponse,
     HttpServer,
     Responder,
+    ResponseError,
     Result as WebResult,
 };
 
@@ -29,28 +41,52 @@ use fst::{
     Streamer,
 };
 
-use prettytable::{
-    Cell,
-    Row,
-    Table,
-};
-
-use quick_xml::{
-    Reader,
-    events::Event,
+use mime::{
+    Mime,
+    APPLICATION_JSON,
+    TEXT_CSV_UTF_8,
+    TEXT_PLAIN_UTF_8,
 };
 
-use mime::TEXT_PLAIN_UTF_8;
-
 use serde_derive::{
     Deserialize,
     Serialize,
 };
 
+use adjacency::AdjacencyBuilder;
+
+use dump::DumpHandler;
+
+use external_sort::ExternalSorter;
+
+use signatures::SignatureBuilder;
+
+pub use dump::open_dump;
+pub use error::Error;
+pub use expression::Expression;
+pub use reader::{
+    sort_pages,
+    Bridge,
+    Communities,
+    Community,
+    CooccurrenceMatrix,
+    EditorMatch,
+    EditorMatches,
+    ExpandedUser,
+    Expansion,
+    FollowedPage,
+    Follows,
+    IndexReader,
+    PageMatch,
+    PageMatches,
+    SimilarCandidate,
+    SimilarUser,
+    UserPage,
+};
+pub use verify::verify;
+
 use std::{
-    cmp::Reverse,
     collections::{
-        BinaryHeap,
         BTreeMap,
         HashMap,
         HashSet,
@@ -58,372 +94,1727 @@ use std::{
     fs::File,
     io::{
         BufRead,
-        Cursor,
+        BufReader,
+        BufWriter,
         Read,
         Seek,
         SeekFrom,
         Write,
     },
     path::Path,
-    sync::Mutex,
+    sync::{
+        Arc,
+        Mutex,
+    },
     time::Instant,
 };
 
-enum Tag {
-    Title,
-    UserName,
-    Other,
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Deserialize)]
+#[derive(PartialEq)]
+pub enum Order {
+    alphabetical,
+    count_decreasing,
+    count_increasing,
+    none,
+    /// Pages modified by more of the users and by fewer editors in total first
+    rarity,
+}
+
+/// Format of query results
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Deserialize)]
+#[derive(PartialEq)]
+pub enum Format {
+    /// One "title: count (editors)" line per page, or a table for the co-occurrences matrix
+    text,
+    /// An array of pages, or the users and the matrix of their counts
+    json,
+    /// One page, or one pair of users, per line
+    jsonl,
+    /// One page, or one row of the matrix, per record, with a header
+    csv,
+    tsv,
+    /// Wikitext table, with links to the pages and to the contributions of the editors
+    wikitext,
+    /// Graphviz graph of the co-occurrences matrix, with users as nodes and pages in common as
+    /// weighted edges
+    dot,
+    /// GraphML graph of the co-occurrences matrix
+    graphml,
+    /// GEXF graph of the co-occurrences matrix, for Gephi
+    gexf,
+}
+
+impl Format {
+    // Whether the format is a graph, which can only be used for the co-occurrences matrix
+    pub(crate) fn is_graph(&self) -> bool {
+        matches!(self, Format::dot | Format::graphml | Format::gexf)
+    }
+}
+
+// Graphs only make sense for the co-occurrences matrix, other results have to
+// be written in other formats
+fn check_list_format(format: Format) -> Result<(), Error> {
+    if format.is_graph() {
+        Err(Error::InvalidQuery("graph formats can only be used for the co-occurrences matrix".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Measure of the similarity of two users in the co-occurrences matrix, from
+/// the number of pages they have both modified and the number of pages each of
+/// them has modified
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Deserialize)]
+#[derive(PartialEq)]
+pub enum Metric {
+    /// Number of pages modified by both users
+    count,
+    /// Pages modified by both users over pages modified by any of them
+    jaccard,
+    /// Pages modified by both users over pages modified by the least active of them
+    overlap,
+    /// Pages modified by both users over the geometric mean of the pages modified by each of them
+    cosine,
+    /// Pages modified by both users over the arithmetic mean of the pages modified by each of them
+    dice,
+    /// Opposite of the decimal logarithm of the p-value of the number of pages modified by both
+    /// users, under a model where each user modifies pages at random (the p-values are shown)
+    significance,
+}
+
+impl Metric {
+    /// Name of the metric, as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::count => "count",
+            Metric::jaccard => "jaccard",
+            Metric::overlap => "overlap",
+            Metric::cosine => "cosine",
+            Metric::dice => "dice",
+            Metric::significance => "significance",
+        }
+    }
+}
+
+/// Restrictions on the edits considered by a query
+#[derive(Default)]
+pub struct Filter {
+    /// Only consider edits made on or after this time (seconds since the Unix epoch)
+    pub since: Option<u32>,
+    /// Only consider edits made on or before this time (seconds since the Unix epoch)
+    pub until: Option<u32>,
+    /// Only consider pages in these namespaces (ids or localized names), or in all of them if empty
+    pub namespaces: Vec<String>,
+    /// Don't consider pages in these namespaces (ids or localized names)
+    pub excluded_namespaces: Vec<String>,
+    /// Don't consider pages modified by more than this number of distinct users (searched for or not)
+    pub max_page_editors: Option<u32>,
+}
+
+/// Settings of an index build
+#[derive(Default)]
+pub struct BuildOptions {
+    /// Approximate amount of memory (in bytes) used to collect the postings
+    /// and the titles before spilling them to temporary files, or no limit if
+    /// None
+    pub memory_budget: Option<u64>,
+    /// Time of the build (in seconds since the Unix epoch) to record in the
+    /// index, or the current time if None
+    pub build_time: Option<u32>,
+    /// Whether to compute the MinHash signatures of the users, to estimate
+    /// their similarity quickly (see [`IndexReader::similar_candidates`])
+    pub signatures: bool,
+    /// Whether to record which users' revisions directly follow which other
+    /// users' on each page (see [`IndexReader::follows`])
+    pub adjacency: bool,
+}
+
+/// Sections of an existing index which an update hasn't been able to keep
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct UpdateSummary {
+    /// Whether the existing index had a revision adjacency (see
+    /// [`BuildOptions::adjacency`]), which the new index doesn't have
+    pub dropped_adjacency: bool,
+}
+
+/// Information about an index and the dump it has been built from
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Metadata {
+    /// Database name of the wiki (eg. frwiki)
+    pub dbname: String,
+    /// URL of the main page of the wiki
+    pub base_url: String,
+    /// Time of the latest revision in the dump (in seconds since the Unix epoch)
+    pub dump_time: u32,
+    /// Time of the build (in seconds since the Unix epoch)
+    pub build_time: u32,
+    /// Version of socksfinder which has built the index
+    pub socksfinder_version: String,
+    pub page_count: u64,
+    pub user_count: u64,
+    pub revision_count: u64,
+}
+
+impl Metadata {
+    // Describe the index in a few words, eg. "frwiki dump of 2023-03-20"
+    fn description(&self) -> String {
+        format!("{} dump of {}", if self.dbname.is_empty() { "unknown wiki" } else { &self.dbname }, &format_timestamp(self.dump_time)[..10])
+    }
+}
+
+const SF_IDENTIFIER_LENGTH: usize = 2;
+const SF_IDENTIFIER: [u8; SF_IDENTIFIER_LENGTH] = [0x53, 0x46];
+const SF_HEADER_LENGTH: u64 = 4;
+const SF_VERSION: u16 = 5;
+const SF_VERSION_32_BITS: u16 = 0;
+const SF_VERSION_WITHOUT_TIMESTAMPS: u16 = 1;
+const SF_VERSION_WITHOUT_NAMESPACES: u16 = 2;
+const SF_VERSION_WITHOUT_EDITOR_COUNTS: u16 = 3;
+const SF_VERSION_WITHOUT_PAGE_EDITORS: u16 = 4;
+const SF_SECTION_FST: usize = 0;
+const SF_SECTION_NAMESPACES: usize = 1;
+const SF_SECTION_METADATA: usize = 2;
+const SF_SECTION_USERS: usize = 3;
+const SF_SECTION_PAGE_EDITORS: usize = 4;
+const SF_SECTION_TITLES: usize = 5;
+const SF_SECTION_SIGNATURES: usize = 6;
+const SF_SECTION_EDIT_COUNTS: usize = 7;
+const SF_SECTION_ADJACENCY: usize = 8;
+
+pub trait Index: BufRead + Seek {}
+impl<T: BufRead + Seek> Index for T {}
+
+pub fn version() -> &'static str {
+    if env!("CARGO_PKG_VERSION").ends_with("-dev") {
+        concat!(env!("CARGO_PKG_VERSION"), "+", env!("VERGEN_GIT_SHA_SHORT"))
+    } else {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+/// Format seconds since the Unix epoch as a MediaWiki timestamp (eg. 2001-01-21T02:12:21Z)
+pub fn format_timestamp(timestamp: u32) -> String {
+    let (year, month, day) = civil_from_days(timestamp as i64 / 86400);
+    let seconds = timestamp % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// Parse a MediaWiki timestamp (eg. 2001-01-21T02:12:21Z) as seconds since the Unix epoch
+fn parse_timestamp(timestamp: &str) -> Option<u32> {
+    let bytes = timestamp.as_bytes();
+    if bytes.len() != 20 ||
+       bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' ||
+       bytes[13] != b':' || bytes[16] != b':' || bytes[19] != b'Z' {
+        return None;
+    }
+    let field = |start: usize, end: usize| -> Option<i64> {
+        timestamp.get(start..end)?.parse().ok()
+    };
+    let (year, month, day) = (field(0, 4)?, field(5, 7)?, field(8, 10)?);
+    let (hours, minutes, seconds) = (field(11, 13)?, field(14, 16)?, field(17, 19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) ||
+       hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let timestamp = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+    u32::try_from(timestamp).ok()
+}
+
+/// Parse a date (eg. 2020-05-06) or a timestamp (eg. 2020-05-06T12:00:00Z)
+/// as seconds since the Unix epoch. A date alone stands for the first second
+/// of that day, or for the last one if end_of_day is true.
+pub fn parse_date(date: &str, end_of_day: bool) -> Option<u32> {
+    if date.len() == 10 {
+        parse_timestamp(&format!("{}T{}Z", date, if end_of_day { "23:59:59" } else { "00:00:00" }))
+    } else {
+        parse_timestamp(date)
+    }
+}
+
+struct Posting {
+    page_offset: u64,
+    first_edit: u32,
+    last_edit: u32,
+}
+
+// Rough memory cost of a user in the map of postings, in addition to its name
+const SF_USER_MEMORY_OVERHEAD: u64 = 64;
+const SF_POSTING_MEMORY_COST: u64 = std::mem::size_of::<Posting>() as u64;
+
+// Add a posting at the end of a list of postings, unless it's on the same
+// page as the last one in which case both are merged; return true if the
+// posting has been added
+fn append_posting(postings: &mut Vec<Posting>, posting: Posting) -> bool {
+    match postings.last_mut() {
+        Some(last_posting) if last_posting.page_offset == posting.page_offset => {
+            last_posting.first_edit = last_posting.first_edit.min(posting.first_edit);
+            last_posting.last_edit = last_posting.last_edit.max(posting.last_edit);
+            false
+        },
+        _ => {
+            postings.push(posting);
+            true
+        },
+    }
+}
+
+struct OffsetWriter<'a> {
+    writer: &'a mut dyn Write,
+    offset: u64,
+}
+
+impl Write for OffsetWriter<'_> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        let length = self.writer.write(buffer)?;
+        self.offset += length as u64;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn write_postings(writer: &mut OffsetWriter, postings: &[Posting]) -> std::io::Result<u64> {
+    let postings_offset = writer.offset;
+    writer.write_u64::<byteorder::LittleEndian>(postings.len() as u64)?;
+    for posting in postings {
+        writer.write_u64::<byteorder::LittleEndian>(posting.page_offset)?;
+        writer.write_u32::<byteorder::LittleEndian>(posting.first_edit)?;
+        writer.write_u32::<byteorder::LittleEndian>(posting.last_edit)?;
+    }
+    Ok(postings_offset)
+}
+
+// Page whose record is written once all its revisions have been read, to know
+// how many distinct editors it has
+struct CurrentPage {
+    namespace: i32,
+    title: String,
+    // with their number of revisions of the page
+    editors: HashMap<Vec<u8>, u32>,
+}
+
+struct IndexBuilder<'a, 'b> {
+    writer: &'b mut OffsetWriter<'a>,
+    options: &'b BuildOptions,
+    current_offset: u64,
+    current_page: Option<CurrentPage>,
+    // editors of each page, by name, until the users are numbered
+    page_editors: BufWriter<File>,
+    adjacency_builder: Option<AdjacencyBuilder>,
+    // titles, with the index of their pages
+    titles: ExternalSorter,
+    user_postings: BTreeMap<Vec<u8>, Vec<Posting>>,
+    user_postings_memory: u64,
+    runs: Vec<File>,
+    namespaces: BTreeMap<i32, String>,
+    metadata: Metadata,
+}
+
+impl DumpHandler for IndexBuilder<'_, '_> {
+    fn site(&mut self, dbname: &str, base_url: &str) {
+        self.metadata.dbname = dbname.to_string();
+        self.metadata.base_url = base_url.to_string();
+    }
+
+    fn namespace(&mut self, key: i32, name: &str) {
+        self.namespaces.insert(key, name.to_string());
+    }
+
+    fn page(&mut self, namespace: i32, title: &str) -> Result<(), Error> {
+        self.finish_page()?;
+        self.current_offset = self.writer.offset;
+        self.current_page = Some(CurrentPage {
+            namespace,
+            title: title.to_string(),
+            editors: HashMap::new(),
+        });
+        self.metadata.page_count += 1;
+        Ok(())
+    }
+
+    fn revision(&mut self, user: &str, timestamp: u32) -> Result<(), Error> {
+        self.metadata.dump_time = self.metadata.dump_time.max(timestamp);
+        self.metadata.revision_count += 1;
+        if let Some(adjacency_builder) = &mut self.adjacency_builder {
+            adjacency_builder.revision(user.as_bytes(), timestamp);
+        }
+        if let Some(current_page) = &mut self.current_page {
+            match current_page.editors.get_mut(user.as_bytes()) {
+                Some(edit_count) => *edit_count += 1,
+                None => {
+                    current_page.editors.insert(user.as_bytes().to_vec(), 1);
+                },
+            }
+        }
+        let postings = self.user_postings.entry(user.as_bytes().to_vec()).or_insert_with(|| {
+            self.user_postings_memory += user.len() as u64 + SF_USER_MEMORY_OVERHEAD;
+            Vec::new()
+        });
+        if append_posting(postings, Posting {
+            page_offset: self.current_offset,
+            first_edit: timestamp,
+            last_edit: timestamp,
+        }) {
+            self.user_postings_memory += SF_POSTING_MEMORY_COST;
+        }
+        if self.options.memory_budget.is_some_and(|memory_budget| self.user_postings_memory + self.titles.memory() > memory_budget) {
+            self.runs.push(external_sort::spill(&mut self.user_postings)?);
+            self.user_postings_memory = 0;
+            self.titles.spill()?;
+        }
+        Ok(())
+    }
+}
+
+impl IndexBuilder<'_, '_> {
+    // Write the record of the current page, at the offset its postings refer
+    // to, and keep its editors aside
+    fn finish_page(&mut self) -> std::io::Result<()> {
+        if let Some(page) = self.current_page.take() {
+            write_page_record(self.writer, page.namespace, page.editors.len() as u32, &page.title)?;
+            self.page_editors.write_u64::<byteorder::LittleEndian>(self.current_offset)?;
+            self.page_editors.write_u32::<byteorder::LittleEndian>(page.editors.len() as u32)?;
+            for (editor, edit_count) in &page.editors {
+                self.page_editors.write_u32::<byteorder::LittleEndian>(editor.len() as u32)?;
+                self.page_editors.write_all(editor)?;
+                self.page_editors.write_u32::<byteorder::LittleEndian>(*edit_count)?;
+            }
+            if let Some(adjacency_builder) = &mut self.adjacency_builder {
+                adjacency_builder.finish_page(self.current_offset)?;
+            }
+            // the current page has already been counted
+            self.titles.push(page.title.into_bytes(), (self.metadata.page_count - 1).to_le_bytes().to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+/// Build an index from one or several MediaWiki XML dumps, read in order
+/// (eg. the parts of a split history dump).
+pub fn build(readers: &mut [&mut dyn BufRead], writer: &mut dyn Write, options: &BuildOptions) -> Result<(), Error> {
+    let mut writer = OffsetWriter {
+        writer,
+        offset: 0,
+    };
+    writer.write_all(&SF_IDENTIFIER)?;
+    writer.write_u16::<byteorder::LittleEndian>(SF_VERSION)?;
+    let mut builder = IndexBuilder {
+        writer: &mut writer,
+        options,
+        current_offset: SF_HEADER_LENGTH,
+        current_page: None,
+        page_editors: BufWriter::new(tempfile::tempfile()?),
+        adjacency_builder: if options.adjacency { Some(AdjacencyBuilder::new()?) } else { None },
+        titles: ExternalSorter::new(None),
+        user_postings: BTreeMap::new(),
+        user_postings_memory: 0,
+        runs: vec![],
+        namespaces: BTreeMap::new(),
+        metadata: Metadata {
+            build_time: options.build_time.unwrap_or_else(current_time),
+            socksfinder_version: version().to_string(),
+            ..Default::default()
+        },
+    };
+    for reader in readers.iter_mut() {
+        dump::parse(*reader, &mut builder)?;
+    }
+    builder.finish_page()?;
+    let IndexBuilder {
+        page_editors,
+        adjacency_builder,
+        titles,
+        mut user_postings,
+        runs,
+        namespaces,
+        mut metadata,
+        ..
+    } = builder;
+    let fst;
+    if runs.is_empty() {
+        metadata.user_count = user_postings.len() as u64;
+        let mut postings_offsets = Vec::with_capacity(user_postings.len());
+        for postings in user_postings.values_mut() {
+            postings_offsets.push(write_postings(&mut writer, postings)?);
+            *postings = Vec::new();
+        }
+        let mut fst_builder = MapBuilder::memory();
+        for (user, postings_offset) in user_postings.into_keys().zip(postings_offsets) {
+            fst_builder.insert(user, postings_offset)?;
+        }
+        fst = fst_builder.into_map();
+    } else {
+        (fst, metadata.user_count) = merge_runs(&mut writer, &mut user_postings, runs)?;
+    }
+    let fst_section = write_fst(&mut writer, &fst)?;
+    let users_section = write_users(&mut writer, &fst)?;
+    // users are numbered in the order of the FST, which is the order of their postings
+    let postings_offsets: Vec<u64> = fst.stream().into_values();
+    let page_editors_offset = writer.offset;
+    let mut page_editors = BufReader::new(page_editors.into_inner().map_err(|error| error.into_error())?);
+    page_editors.seek(SeekFrom::Start(0))?;
+    let mut entry_offsets = Vec::with_capacity(metadata.page_count as usize);
+    let mut signature_builder = options.signatures.then(|| SignatureBuilder::new(fst.len()));
+    let mut edit_counts = vec![0; fst.len()];
+    let mut editors = vec![];
+    let mut editor = vec![];
+    while let Ok(page_offset) = page_editors.read_u64::<byteorder::LittleEndian>() {
+        let editor_count = page_editors.read_u32::<byteorder::LittleEndian>()?;
+        for _ in 0..editor_count {
+            editor.resize(page_editors.read_u32::<byteorder::LittleEndian>()? as usize, 0);
+            page_editors.read_exact(&mut editor)?;
+            let postings_offset = fst.get(&editor).unwrap();
+            let user_number = postings_offsets.binary_search(&postings_offset).unwrap();
+            edit_counts[user_number] += page_editors.read_u32::<byteorder::LittleEndian>()? as u64;
+            editors.push(user_number as u32);
+        }
+        editors.sort_unstable();
+        entry_offsets.push(write_page_editors(&mut writer, page_offset, &editors)?);
+        if let Some(signature_builder) = &mut signature_builder {
+            signature_builder.add_page(page_offset, &editors);
+        }
+        editors.clear();
+    }
+    let page_editors_section = Section {
+        offset: page_editors_offset,
+        length: writer.offset - page_editors_offset,
+    };
+    let mut sections = vec![users_section, page_editors_section, write_titles(&mut writer, titles, &entry_offsets)?];
+    sections.push(match signature_builder {
+        Some(signature_builder) => signature_builder.write(&mut writer)?,
+        None => Section::default(),
+    });
+    sections.push(write_edit_counts(&mut writer, &edit_counts)?);
+    if let Some(adjacency_builder) = adjacency_builder {
+        sections.push(adjacency_builder.write(&mut writer, fst.len(), options.memory_budget, |user| {
+            postings_offsets.binary_search(&fst.get(user).unwrap()).unwrap() as u32
+        })?);
+    }
+    write_sections(&mut writer, fst_section, &sections, &namespaces, &metadata)?;
+    Ok(())
+}
+
+fn write_fst(writer: &mut OffsetWriter, fst: &fst::Map<Vec<u8>>) -> std::io::Result<Section> {
+    let fst_offset = writer.offset;
+    writer.write_all(fst.as_fst().as_bytes())?;
+    Ok(Section {
+        offset: fst_offset,
+        length: writer.offset - fst_offset,
+    })
+}
+
+// Write the names of the users, in the order of the FST, after the offset of
+// each of them, so that the editors of a page can be given by number
+fn write_users(writer: &mut OffsetWriter, fst: &fst::Map<Vec<u8>>) -> std::io::Result<Section> {
+    let users_offset = writer.offset;
+    let mut user_offset = users_offset + 8 * fst.len() as u64;
+    let mut users = fst.keys();
+    while let Some(user) = users.next() {
+        writer.write_u64::<byteorder::LittleEndian>(user_offset)?;
+        user_offset += user.len() as u64 + 1;
+    }
+    let mut users = fst.keys();
+    while let Some(user) = users.next() {
+        writer.write_all(user)?;
+        writer.write_u8(0xA)?;
+    }
+    Ok(Section {
+        offset: users_offset,
+        length: writer.offset - users_offset,
+    })
+}
+
+// Write the number of revisions of each user, in the order of the FST
+fn write_edit_counts(writer: &mut OffsetWriter, edit_counts: &[u64]) -> std::io::Result<Section> {
+    let edit_counts_offset = writer.offset;
+    for edit_count in edit_counts {
+        writer.write_u64::<byteorder::LittleEndian>(*edit_count)?;
+    }
+    Ok(Section {
+        offset: edit_counts_offset,
+        length: writer.offset - edit_counts_offset,
+    })
+}
+
+// Write the entry of a page in the page editors section: the offset of its
+// record, its number of editors and their numbers, sorted; return the offset
+// of the entry
+fn write_page_editors(writer: &mut OffsetWriter, page_offset: u64, editors: &[u32]) -> std::io::Result<u64> {
+    let entry_offset = writer.offset;
+    writer.write_u64::<byteorder::LittleEndian>(page_offset)?;
+    writer.write_u32::<byteorder::LittleEndian>(editors.len() as u32)?;
+    for editor in editors {
+        writer.write_u32::<byteorder::LittleEndian>(*editor)?;
+    }
+    Ok(entry_offset)
+}
+
+// Write the FST of the titles, to the entries of their pages in the page
+// editors section (only the first page is kept if several have the same title,
+// as titles are pushed in the order of their pages)
+fn write_titles(writer: &mut OffsetWriter, titles: ExternalSorter, entry_offsets: &[u64]) -> Result<Section, Error> {
+    let mut titles = titles.finish()?;
+    let titles_offset = writer.offset;
+    let mut fst_builder = MapBuilder::new(&mut *writer)?;
+    let mut previous_title = None;
+    while let Some((title, page_index)) = titles.next_record()? {
+        if previous_title.as_ref() == Some(&title) {
+            continue;
+        }
+        let page_index = u64::from_le_bytes(page_index.try_into().unwrap()) as usize;
+        fst_builder.insert(&title, entry_offsets[page_index])?;
+        previous_title = Some(title);
+    }
+    fst_builder.finish()?;
+    Ok(Section {
+        offset: titles_offset,
+        length: writer.offset - titles_offset,
+    })
+}
+
+fn current_time() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as u32)
+}
+
+// Write the namespaces and the metadata, then the footer listing them along
+// with the FST and the sections written after it (from the users section on)
+fn write_sections(writer: &mut OffsetWriter, fst_section: Section, other_sections: &[Section], namespaces: &BTreeMap<i32, String>, metadata: &Metadata) -> std::io::Result<()> {
+    let mut sections = vec![fst_section];
+    let namespaces_offset = writer.offset;
+    for (key, name) in namespaces {
+        writer.write_i32::<byteorder::LittleEndian>(*key)?;
+        writer.write_all(name.as_bytes())?;
+        writer.write_u8(0xA)?;
+    }
+    sections.push(Section {
+        offset: namespaces_offset,
+        length: writer.offset - namespaces_offset,
+    });
+    // one "key=value" line per field, so that fields can be added without breaking older readers
+    let metadata_offset = writer.offset;
+    writeln!(writer, "dbname={}", metadata.dbname)?;
+    writeln!(writer, "base_url={}", metadata.base_url)?;
+    writeln!(writer, "dump_time={}", metadata.dump_time)?;
+    writeln!(writer, "build_time={}", metadata.build_time)?;
+    writeln!(writer, "socksfinder_version={}", metadata.socksfinder_version)?;
+    writeln!(writer, "page_count={}", metadata.page_count)?;
+    writeln!(writer, "user_count={}", metadata.user_count)?;
+    writeln!(writer, "revision_count={}", metadata.revision_count)?;
+    sections.push(Section {
+        offset: metadata_offset,
+        length: writer.offset - metadata_offset,
+    });
+    sections.extend_from_slice(other_sections);
+    // sections which aren't there are empty, they only have to be listed
+    // when a later section is there
+    while sections.last().is_some_and(|section| section.length == 0) {
+        sections.pop();
+    }
+    for section in &sections {
+        writer.write_u64::<byteorder::LittleEndian>(section.offset)?;
+        writer.write_u64::<byteorder::LittleEndian>(section.length)?;
+    }
+    writer.write_u64::<byteorder::LittleEndian>(sections.len() as u64)?;
+    Ok(())
+}
+
+// Write the postings from the runs spilled during the build and the postings
+// still in memory, and return the FST of the users along with their number
+fn merge_runs(writer: &mut OffsetWriter, user_postings: &mut BTreeMap<Vec<u8>, Vec<Posting>>, mut runs: Vec<File>) -> Result<(fst::Map<Vec<u8>>, u64), Error> {
+    runs.push(external_sort::spill(user_postings)?);
+    let mut merger = external_sort::RunMerger::new(runs)?;
+    let mut postings = vec![];
+    let mut user_offsets = external_sort::UserOffsets::new()?;
+    let mut user_count = 0;
+    while let Some(user) = merger.next_user(&mut postings)? {
+        user_offsets.push(&user, write_postings(writer, &postings)?)?;
+        user_count += 1;
+        postings.clear();
+    }
+    let mut fst_bytes = vec![];
+    user_offsets.write_fst(&mut fst_bytes)?;
+    Ok((fst::Map::new(fst_bytes)?, user_count))
+}
+
+fn write_page_record(writer: &mut dyn Write, namespace: i32, editor_count: u32, title: &str) -> std::io::Result<()> {
+    writer.write_i32::<byteorder::LittleEndian>(namespace)?;
+    writer.write_u32::<byteorder::LittleEndian>(editor_count)?;
+    writer.write_all(title.as_bytes())?;
+    writer.write_u8(0xA)
+}
+
+struct UpdatedPage {
+    namespace: i32,
+    title: String,
+    // whether the page is already in the existing index
+    existing: bool,
+    page_offset: u64,
+    editor_count: u32,
+}
+
+struct IndexUpdater {
+    pages: Vec<UpdatedPage>,
+    page_indexes: HashMap<i32, HashMap<String, usize>>,
+    current_page: usize,
+    memory_budget: Option<u64>,
+    // until the offsets of the pages are known, postings refer to their index in pages
+    user_postings: BTreeMap<Vec<u8>, Vec<Posting>>,
+    user_postings_memory: u64,
+    runs: Vec<File>,
+    // number of newer revisions of each user
+    edit_counts: HashMap<Vec<u8>, u64>,
+    namespaces: BTreeMap<i32, String>,
+    // what is known of the newer revisions only
+    metadata: Metadata,
+}
+
+impl DumpHandler for IndexUpdater {
+    fn site(&mut self, dbname: &str, base_url: &str) {
+        self.metadata.dbname = dbname.to_string();
+        self.metadata.base_url = base_url.to_string();
+    }
+
+    fn namespace(&mut self, key: i32, name: &str) {
+        self.namespaces.insert(key, name.to_string());
+    }
+
+    fn page(&mut self, namespace: i32, title: &str) -> Result<(), Error> {
+        let page_indexes = self.page_indexes.entry(namespace).or_default();
+        self.current_page = match page_indexes.get(title) {
+            Some(page_index) => *page_index,
+            None => {
+                self.pages.push(UpdatedPage {
+                    namespace,
+                    title: title.to_string(),
+                    existing: false,
+                    page_offset: 0,
+                    editor_count: 0,
+                });
+                page_indexes.insert(title.to_string(), self.pages.len() - 1);
+                self.pages.len() - 1
+            },
+        };
+        Ok(())
+    }
+
+    fn revision(&mut self, user: &str, timestamp: u32) -> Result<(), Error> {
+        self.metadata.dump_time = self.metadata.dump_time.max(timestamp);
+        self.metadata.revision_count += 1;
+        let postings = self.user_postings.entry(user.as_bytes().to_vec()).or_insert_with(|| {
+            self.user_postings_memory += user.len() as u64 + SF_USER_MEMORY_OVERHEAD;
+            Vec::new()
+        });
+        if append_posting(postings, Posting {
+            page_offset: self.current_page as u64,
+            first_edit: timestamp,
+            last_edit: timestamp,
+        }) {
+            self.user_postings_memory += SF_POSTING_MEMORY_COST;
+        }
+        *self.edit_counts.entry(user.as_bytes().to_vec()).or_default() += 1;
+        if self.memory_budget.is_some_and(|memory_budget| self.user_postings_memory > memory_budget) {
+            self.runs.push(external_sort::spill(&mut self.user_postings)?);
+            self.user_postings_memory = 0;
+        }
+        Ok(())
+    }
+}
+
+// Merge two lists of postings sorted by page
+fn merge_postings(first_postings: Vec<Posting>, second_postings: Vec<Posting>) -> Vec<Posting> {
+    let mut postings = Vec::with_capacity(first_postings.len() + second_postings.len());
+    let mut first_postings = first_postings.into_iter().peekable();
+    let mut second_postings = second_postings.into_iter().peekable();
+    loop {
+        let posting = match (first_postings.peek(), second_postings.peek()) {
+            (None, None) => break,
+            (Some(first_posting), Some(second_posting)) if second_posting.page_offset < first_posting.page_offset => second_postings.next(),
+            (Some(_), _) => first_postings.next(),
+            (None, Some(_)) => second_postings.next(),
+        };
+        append_posting(&mut postings, posting.unwrap());
+    }
+    postings
+}
+
+/// Build a new index from an existing index and a dump of newer revisions.
+/// Pages, postings and users of the existing index are kept, pages which are
+/// not in the existing index are added after them and postings of the newer
+/// revisions are merged with the existing ones. The postings of the newer
+/// revisions are collected within the memory budget, as for a build (see
+/// [`BuildOptions::memory_budget`]). The revision adjacency isn't kept, as
+/// the revisions the newer ones directly follow aren't known, which the
+/// returned summary tells if the existing index had it.
+pub fn update(index: &mut dyn Index, reader: &mut dyn BufRead, writer: &mut dyn Write, memory_budget: Option<u64>) -> Result<UpdateSummary, Error> {
+    let header = read_index_header(index)?;
+    if header.version != SF_VERSION {
+        return Err(Error::OutdatedIndex(format!("can't update index in format version {}, only indexes in format version {} can be updated (rebuild it instead)", header.version, SF_VERSION)));
+    }
+    let summary = UpdateSummary {
+        dropped_adjacency: header.section(SF_SECTION_ADJACENCY).is_some(),
+    };
+    let mut updater = IndexUpdater {
+        pages: vec![],
+        page_indexes: HashMap::new(),
+        current_page: 0,
+        memory_budget,
+        user_postings: BTreeMap::new(),
+        user_postings_memory: 0,
+        runs: vec![],
+        edit_counts: HashMap::new(),
+        namespaces: BTreeMap::new(),
+        metadata: Metadata::default(),
+    };
+    dump::parse(reader, &mut updater)?;
+    let mut metadata = read_metadata(index, &header)?.unwrap_or_default();
+    if !updater.metadata.dbname.is_empty() {
+        metadata.dbname = updater.metadata.dbname;
+        metadata.base_url = updater.metadata.base_url;
+    }
+    metadata.dump_time = metadata.dump_time.max(updater.metadata.dump_time);
+    metadata.build_time = current_time();
+    metadata.socksfinder_version = version().to_string();
+    metadata.revision_count += updater.metadata.revision_count;
+    let fst = read_fst(index, &header)?;
+    let page_names_end_offset = page_names_end_offset(&header, &fst)?;
+    let old_page_count = locate_pages(index, page_names_end_offset, &mut updater.pages, &updater.page_indexes)?;
+    let new_page_offsets: Vec<_> = updater.pages.iter()
+        .filter(|page| !page.existing)
+        .map(|page| page.page_offset)
+        .collect();
+    metadata.page_count = old_page_count + new_page_offsets.len() as u64;
+    updater.runs.push(external_sort::spill(&mut updater.user_postings)?);
+    let new_postings = count_new_editors(index, &header, &fst, &mut updater.pages, updater.runs)?;
+    let mut writer = OffsetWriter {
+        writer,
+        offset: 0,
+    };
+    writer.write_all(&SF_IDENTIFIER)?;
+    writer.write_u16::<byteorder::LittleEndian>(SF_VERSION)?;
+    let new_titles = write_page_records(index, &mut writer, page_names_end_offset, &updater.pages, memory_budget)?;
+    let mut merged_users = merge_users(index, &header, &fst, &mut writer, new_postings, &updater.edit_counts)?;
+    metadata.user_count = merged_users.fst.len() as u64;
+    let fst_section = write_fst(&mut writer, &merged_users.fst)?;
+    let users_section = write_users(&mut writer, &merged_users.fst)?;
+    // signatures are kept up to date if the index has them
+    let mut signature_builder = header.section(SF_SECTION_SIGNATURES).map(|_| SignatureBuilder::new(merged_users.fst.len()));
+    let (page_editors_section, old_entry_offsets, entry_offsets) = merge_page_editors(index, &header, &mut writer, &mut merged_users, &new_page_offsets, &mut signature_builder)?;
+    if old_entry_offsets.len() as u64 != old_page_count {
+        return Err(Error::Corrupt(format!("the page editors section has {} entries, but the index has {} pages", old_entry_offsets.len(), old_page_count)));
+    }
+    let titles_section = merge_titles(index, &header, &mut writer, new_titles, &old_entry_offsets, &entry_offsets)?;
+    let mut sections = vec![users_section, page_editors_section, titles_section];
+    sections.push(match signature_builder {
+        Some(signature_builder) => signature_builder.write(&mut writer)?,
+        None => Section::default(),
+    });
+    if let Some(edit_counts) = merged_users.edit_counts {
+        sections.push(write_edit_counts(&mut writer, &edit_counts)?);
+    }
+    let mut namespaces = read_namespaces(index, &header)?;
+    namespaces.extend(updater.namespaces);
+    write_sections(&mut writer, fst_section, &sections, &namespaces, &metadata)?;
+    Ok(summary)
+}
+
+// Find the pages of the newer revisions among the page records of the
+// existing index, whose offsets and editor counts they get, and give the new
+// pages the offsets of their records after the existing ones. Return the
+// number of pages of the existing index.
+fn locate_pages(index: &mut dyn Index, page_names_end_offset: u64, pages: &mut [UpdatedPage], page_indexes: &HashMap<i32, HashMap<String, usize>>) -> Result<u64, Error> {
+    index.seek(SeekFrom::Start(SF_HEADER_LENGTH))?;
+    let mut page_reader = index.take(page_names_end_offset - SF_HEADER_LENGTH);
+    let mut page_offset = SF_HEADER_LENGTH;
+    let mut page_count = 0;
+    let mut title = String::new();
+    while let Ok(namespace) = page_reader.read_i32::<byteorder::LittleEndian>() {
+        let editor_count = page_reader.read_u32::<byteorder::LittleEndian>()?;
+        page_reader.read_line(&mut title)?;
+        let record_length = 8 + title.len() as u64;
+        title.pop();
+        if let Some(page_index) = page_indexes.get(&namespace).and_then(|page_indexes| page_indexes.get(&title)) {
+            pages[*page_index].existing = true;
+            pages[*page_index].page_offset = page_offset;
+            pages[*page_index].editor_count = editor_count;
+        }
+        page_count += 1;
+        page_offset += record_length;
+        title.clear();
+    }
+    if page_offset != page_names_end_offset {
+        return Err(Error::Corrupt(format!("page records end at offset {}, but postings start at offset {}", page_offset, page_names_end_offset)));
+    }
+    for page in pages.iter_mut().filter(|page| !page.existing) {
+        page.page_offset = page_offset;
+        page_offset += 9 + page.title.len() as u64;
+    }
+    Ok(page_count)
+}
+
+// Count the new editors of each page, as editors of the newer revisions are
+// only new editors of a page if they haven't modified it in the existing
+// index. Return the postings of the newer revisions, by page offset, in a
+// single run sorted by user.
+fn count_new_editors(index: &mut dyn Index, header: &IndexHeader, fst: &fst::Map<Vec<u8>>, pages: &mut [UpdatedPage], runs: Vec<File>) -> Result<File, Error> {
+    let mut merger = external_sort::RunMerger::new(runs)?;
+    let mut new_postings = external_sort::RunWriter::new()?;
+    let mut postings = vec![];
+    while let Some(user) = merger.next_user(&mut postings)? {
+        let old_postings = match fst.get(&user) {
+            Some(value) => read_postings(index, header, value)?,
+            None => vec![],
+        };
+        let page_indexes: HashSet<_> = postings.iter().map(|posting| posting.page_offset as usize).collect();
+        for page_index in page_indexes {
+            let page = &mut pages[page_index];
+            if !page.existing || old_postings.binary_search_by_key(&page.page_offset, |posting| posting.page_offset).is_err() {
+                page.editor_count += 1;
+            }
+        }
+        let mut page_postings: Vec<_> = postings.drain(..)
+            .map(|posting| Posting {
+                page_offset: pages[posting.page_offset as usize].page_offset,
+                ..posting
+            })
+            .collect();
+        page_postings.sort_unstable_by_key(|posting| posting.page_offset);
+        new_postings.push(&user, &merge_postings(page_postings, vec![]))?;
+    }
+    Ok(new_postings.finish()?)
+}
+
+// Copy the page records of the existing index, which keep their length and so
+// their offset, only changing the editor count of the pages of the newer
+// revisions, then write the records of the new pages. Return the titles of the
+// new pages, with their index among the new pages.
+fn write_page_records(index: &mut dyn Index, writer: &mut OffsetWriter, page_names_end_offset: u64, pages: &[UpdatedPage], memory_budget: Option<u64>) -> Result<ExternalSorter, Error> {
+    let mut existing_pages: Vec<_> = pages.iter().filter(|page| page.existing).collect();
+    existing_pages.sort_unstable_by_key(|page| page.page_offset);
+    index.seek(SeekFrom::Start(SF_HEADER_LENGTH))?;
+    for page in existing_pages {
+        // the editor count follows the namespace
+        copy_index(index, writer, page.page_offset + 4 - writer.offset)?;
+        writer.write_u32::<byteorder::LittleEndian>(page.editor_count)?;
+        index.seek(SeekFrom::Current(4))?;
+    }
+    copy_index(index, writer, page_names_end_offset - writer.offset)?;
+    let mut titles = ExternalSorter::new(memory_budget);
+    for (new_page_index, page) in pages.iter().filter(|page| !page.existing).enumerate() {
+        write_page_record(writer, page.namespace, page.editor_count, &page.title)?;
+        titles.push(page.title.as_bytes().to_vec(), (new_page_index as u64).to_le_bytes().to_vec())?;
+    }
+    Ok(titles)
+}
+
+// Copy bytes of the existing index from its current position
+fn copy_index(index: &mut dyn Index, writer: &mut OffsetWriter, length: u64) -> Result<(), Error> {
+    if std::io::copy(&mut index.take(length), writer)? != length {
+        return Err(Error::Corrupt("unexpected end of index".to_string()));
+    }
+    Ok(())
+}
+
+// Users of the existing index merged with the users of the newer revisions
+struct MergedUsers {
+    fst: fst::Map<Vec<u8>>,
+    // new number of each user of the existing index, which are renumbered as
+    // new users are inserted among them
+    user_numbers: Vec<u32>,
+    // editors of the newer revisions of each page, by page offset
+    new_page_editors: HashMap<u64, Vec<u32>>,
+    // kept up to date if the existing index has them
+    edit_counts: Option<Vec<u64>>,
+}
+
+// Write the postings of the users of the existing index and of the newer
+// revisions, in alphabetical order, copying the postings of the users of the
+// existing index without newer revisions as they are
+fn merge_users(index: &mut dyn Index, header: &IndexHeader, fst: &fst::Map<Vec<u8>>, writer: &mut OffsetWriter, new_postings: File, new_edit_counts: &HashMap<Vec<u8>, u64>) -> Result<MergedUsers, Error> {
+    let old_edit_counts = match header.section(SF_SECTION_EDIT_COUNTS) {
+        Some(section) => Some(read_edit_counts(index, section, fst.len())?),
+        None => None,
+    };
+    let mut merged_users = MergedUsers {
+        fst: fst::Map::default(),
+        user_numbers: Vec::with_capacity(fst.len()),
+        new_page_editors: HashMap::new(),
+        edit_counts: old_edit_counts.as_ref().map(|_| Vec::with_capacity(fst.len())),
+    };
+    let mut user_offsets = external_sort::UserOffsets::new()?;
+    let mut user_count = 0;
+    let mut new_users = external_sort::RunMerger::new(vec![new_postings])?;
+    let mut new_postings = vec![];
+    let mut new_user = new_users.next_user(&mut new_postings)?;
+    let mut old_users = fst.stream();
+    let mut old_user = old_users.next().map(|(user, value)| (user.to_vec(), value));
+    loop {
+        let user_number = user_count as u32;
+        let (user, postings_offset) = match (old_user.take(), new_user.take()) {
+            (None, None) => break,
+            (Some((user, value)), next_new_user) if next_new_user.as_ref().is_none_or(|new_user| user <= *new_user) => {
+                old_user = old_users.next().map(|(user, value)| (user.to_vec(), value));
+                if let (Some(edit_counts), Some(old_edit_counts)) = (&mut merged_users.edit_counts, &old_edit_counts) {
+                    edit_counts.push(old_edit_counts[merged_users.user_numbers.len()]);
+                }
+                merged_users.user_numbers.push(user_number);
+                if next_new_user.as_ref() == Some(&user) {
+                    for posting in &new_postings {
+                        merged_users.new_page_editors.entry(posting.page_offset).or_default().push(user_number);
+                    }
+                    let postings = merge_postings(read_postings(index, header, value)?, std::mem::take(&mut new_postings));
+                    new_user = new_users.next_user(&mut new_postings)?;
+                    (user, write_postings(writer, &postings)?)
+                } else {
+                    new_user = next_new_user;
+                    (user, copy_postings(index, header, writer, value)?)
+                }
+            },
+            (next_old_user, next_new_user) => {
+                old_user = next_old_user;
+                if let Some(edit_counts) = &mut merged_users.edit_counts {
+                    edit_counts.push(0);
+                }
+                for posting in &new_postings {
+                    merged_users.new_page_editors.entry(posting.page_offset).or_default().push(user_number);
+                }
+                let postings_offset = write_postings(writer, &std::mem::take(&mut new_postings))?;
+                new_user = new_users.next_user(&mut new_postings)?;
+                (next_new_user.unwrap(), postings_offset)
+            },
+        };
+        if let Some(edit_counts) = &mut merged_users.edit_counts {
+            *edit_counts.last_mut().unwrap() += new_edit_counts.get(&user).copied().unwrap_or(0);
+        }
+        user_offsets.push(&user, postings_offset)?;
+        user_count += 1;
+    }
+    let mut fst_bytes = vec![];
+    user_offsets.write_fst(&mut fst_bytes)?;
+    merged_users.fst = fst::Map::new(fst_bytes)?;
+    Ok(merged_users)
+}
+
+// Copy the postings of a user of the existing index, which refer to pages
+// whose records have kept their offsets
+fn copy_postings(index: &mut dyn Index, header: &IndexHeader, writer: &mut OffsetWriter, value: u64) -> Result<u64, Error> {
+    index.seek(SeekFrom::Start(value)).map_err(Error::from_index)?;
+    let edit_count = index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+    check_postings(header, value + 8, edit_count, 16)?;
+    let postings_offset = writer.offset;
+    writer.write_u64::<byteorder::LittleEndian>(edit_count)?;
+    copy_index(index, writer, 16 * edit_count)?;
+    Ok(postings_offset)
+}
+
+// Write the entries of the existing pages, in the order of their records, with
+// their editors renumbered and their new editors, then the entries of the new
+// pages. Return the section along with the offsets of the entries of the
+// existing pages in the existing index and of all the entries in the new one.
+fn merge_page_editors(index: &mut dyn Index, header: &IndexHeader, writer: &mut OffsetWriter, merged_users: &mut MergedUsers, new_page_offsets: &[u64], signature_builder: &mut Option<SignatureBuilder>) -> Result<(Section, Vec<u64>, Vec<u64>), Error> {
+    let page_editors_offset = writer.offset;
+    let old_page_editors_section = header.section(SF_SECTION_PAGE_EDITORS).unwrap_or(Section {
+        offset: 0,
+        length: 0,
+    });
+    let mut old_entry_offsets = vec![];
+    let mut entry_offsets = vec![];
+    index.seek(SeekFrom::Start(old_page_editors_section.offset))?;
+    let mut old_page_editors = index.take(old_page_editors_section.length);
+    let mut old_entry_offset = old_page_editors_section.offset;
+    let mut editors = vec![];
+    while let Ok(page_offset) = old_page_editors.read_u64::<byteorder::LittleEndian>() {
+        let editor_count = old_page_editors.read_u32::<byteorder::LittleEndian>()?;
+        for _ in 0..editor_count {
+            let old_user_number = old_page_editors.read_u32::<byteorder::LittleEndian>()? as usize;
+            match merged_users.user_numbers.get(old_user_number) {
+                Some(user_number) => editors.push(*user_number),
+                None => return Err(Error::Corrupt(format!("page at offset {} has editor {}, but there are only {} users", page_offset, old_user_number, merged_users.user_numbers.len()))),
+            }
+        }
+        if let Some(new_editors) = merged_users.new_page_editors.remove(&page_offset) {
+            editors.extend(new_editors);
+            editors.sort_unstable();
+            editors.dedup();
+        }
+        old_entry_offsets.push(old_entry_offset);
+        old_entry_offset += 12 + 4 * editor_count as u64;
+        entry_offsets.push(write_page_editors(writer, page_offset, &editors)?);
+        if let Some(signature_builder) = signature_builder {
+            signature_builder.add_page(page_offset, &editors);
+        }
+        editors.clear();
+    }
+    for page_offset in new_page_offsets {
+        let mut editors = merged_users.new_page_editors.remove(page_offset).unwrap_or_default();
+        editors.sort_unstable();
+        editors.dedup();
+        entry_offsets.push(write_page_editors(writer, *page_offset, &editors)?);
+        if let Some(signature_builder) = signature_builder {
+            signature_builder.add_page(*page_offset, &editors);
+        }
+    }
+    let page_editors_section = Section {
+        offset: page_editors_offset,
+        length: writer.offset - page_editors_offset,
+    };
+    Ok((page_editors_section, old_entry_offsets, entry_offsets))
+}
+
+// Write the FST of the titles, merging the titles of the existing index, whose
+// entries have moved, with the titles of the new pages, whose entries follow
+// the entries of the existing pages. As for builds, only the first page is
+// kept if several have the same title, which is a page of the existing index
+// if there is one.
+fn merge_titles(index: &mut dyn Index, header: &IndexHeader, writer: &mut OffsetWriter, new_titles: ExternalSorter, old_entry_offsets: &[u64], entry_offsets: &[u64]) -> Result<Section, Error> {
+    let old_titles_section = header.section(SF_SECTION_TITLES).ok_or_else(|| Error::Corrupt("the titles section is missing".to_string()))?;
+    index.seek(SeekFrom::Start(old_titles_section.offset))?;
+    let mut old_titles_bytes = vec![];
+    index.take(old_titles_section.length).read_to_end(&mut old_titles_bytes)?;
+    let old_titles = fst::Map::new(old_titles_bytes)?;
+    let mut old_titles = old_titles.stream();
+    let mut old_title = old_titles.next().map(|(title, value)| (title.to_vec(), value));
+    let mut new_titles = new_titles.finish()?;
+    let mut new_title = new_titles.next_record()?;
+    let titles_offset = writer.offset;
+    let mut fst_builder = MapBuilder::new(&mut *writer)?;
+    let mut previous_title = None;
+    loop {
+        let (title, entry_offset) = match (old_title.take(), new_title.take()) {
+            (None, None) => break,
+            (Some((title, old_entry_offset)), next_new_title) if next_new_title.as_ref().is_none_or(|(new_title, _)| title <= *new_title) => {
+                old_title = old_titles.next().map(|(title, value)| (title.to_vec(), value));
+                new_title = next_new_title;
+                match old_entry_offsets.binary_search(&old_entry_offset) {
+                    Ok(page_index) => (title, entry_offsets[page_index]),
+                    Err(_) => return Err(Error::Corrupt(format!("title {} refers to offset {}, which isn't an entry of the page editors section", String::from_utf8_lossy(&title), old_entry_offset))),
+                }
+            },
+            (next_old_title, next_new_title) => {
+                old_title = next_old_title;
+                new_title = new_titles.next_record()?;
+                let (title, new_page_index) = next_new_title.unwrap();
+                let new_page_index = u64::from_le_bytes(new_page_index.try_into().unwrap()) as usize;
+                (title, entry_offsets[old_entry_offsets.len() + new_page_index])
+            },
+        };
+        if previous_title.as_ref() == Some(&title) {
+            continue;
+        }
+        fst_builder.insert(&title, entry_offset)?;
+        previous_title = Some(title);
+    }
+    fst_builder.finish()?;
+    Ok(Section {
+        offset: titles_offset,
+        length: writer.offset - titles_offset,
+    })
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Default)]
+struct Section {
+    offset: u64,
+    length: u64,
+}
+
+struct IndexHeader {
+    version: u16,
+    sections: Vec<Section>,
+    footer_offset: u64,
+}
+
+impl IndexHeader {
+    fn section(&self, id: usize) -> Option<Section> {
+        self.sections.get(id).copied().filter(|section| section.length > 0)
+    }
+
+    fn fst_section(&self) -> Section {
+        self.sections[SF_SECTION_FST]
+    }
+}
+
+fn read_index_header(index: &mut dyn Index) -> Result<IndexHeader, Error> {
+    let mut identifier_bytes = [0u8; SF_IDENTIFIER_LENGTH];
+    match index.read(&mut identifier_bytes) {
+        Ok(length) => {
+            if length != SF_IDENTIFIER_LENGTH ||
+               identifier_bytes != SF_IDENTIFIER {
+                   return Err(Error::BadMagic)
+            }
+        },
+        Err(_) => return Err(Error::BadMagic),
+    }
+    let version = match index.read_u16::<byteorder::LittleEndian>() {
+        Ok(index_version) => {
+            if index_version > SF_VERSION {
+                return Err(Error::UnsupportedVersion(index_version))
+            }
+            index_version
+        },
+        Err(_) => return Err(Error::Corrupt("unable to read index format version number".to_string())),
+    };
+    let index_length = index.seek(SeekFrom::End(0)).map_err(Error::from_index)?;
+    if version <= SF_VERSION_WITHOUT_NAMESPACES {
+        // the footer only holds the offset of the FST, which ends where the footer starts
+        let footer_length = if version == SF_VERSION_32_BITS { 4 } else { 8 };
+        if index_length < SF_HEADER_LENGTH + footer_length {
+            return Err(Error::Corrupt(format!("the index is only {} bytes long, not enough for its footer", index_length)))
+        }
+        let fst_end_offset = index.seek(SeekFrom::End(-(footer_length as i64))).map_err(Error::from_index)?;
+        let fst_start_offset = if version == SF_VERSION_32_BITS {
+            index.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)? as u64
+        } else {
+            index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?
+        };
+        if fst_start_offset < SF_HEADER_LENGTH || fst_start_offset > fst_end_offset {
+            return Err(Error::Corrupt(format!("the footer gives an FST offset of {}, outside of the index (between {} and {})", fst_start_offset, SF_HEADER_LENGTH, fst_end_offset)))
+        }
+        return Ok(IndexHeader {
+            version,
+            sections: vec![Section {
+                offset: fst_start_offset,
+                length: fst_end_offset - fst_start_offset,
+            }],
+            footer_offset: fst_end_offset,
+        });
+    }
+    // the footer holds the offset and length of each section, then the number of sections
+    if index_length < SF_HEADER_LENGTH + 8 {
+        return Err(Error::Corrupt(format!("the index is only {} bytes long, not enough for its footer", index_length)))
+    }
+    index.seek(SeekFrom::End(-8)).map_err(Error::from_index)?;
+    let section_count = index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+    if section_count == 0 || section_count > (index_length - SF_HEADER_LENGTH - 8) / 16 {
+        return Err(Error::Corrupt(format!("the footer lists {} sections, which doesn't fit in an index of {} bytes", section_count, index_length)))
+    }
+    let footer_offset = index.seek(SeekFrom::End(-8 - 16 * section_count as i64)).map_err(Error::from_index)?;
+    let mut sections = Vec::with_capacity(section_count as usize);
+    for section_id in 0..section_count {
+        let section = Section {
+            offset: index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+            length: index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+        };
+        if section.length > 0 &&
+           (section.offset < SF_HEADER_LENGTH || section.offset.checked_add(section.length).is_none_or(|end| end > footer_offset)) {
+            return Err(Error::Corrupt(format!("section {} ({} bytes at offset {}) isn't between the header and the footer (at offset {})", section_id, section.length, section.offset, footer_offset)))
+        }
+        sections.push(section);
+    }
+    Ok(IndexHeader {
+        version,
+        sections,
+        footer_offset,
+    })
+}
+
+// Read the number of revisions of each user, in the order of the FST
+fn read_edit_counts(index: &mut dyn Index, section: Section, user_count: usize) -> Result<Vec<u64>, Error> {
+    if section.length != 8 * user_count as u64 {
+        return Err(Error::Corrupt(format!("the edit counts section is {} bytes long instead of {} for {} users", section.length, 8 * user_count, user_count)));
+    }
+    index.seek(SeekFrom::Start(section.offset)).map_err(Error::from_index)?;
+    let mut edit_counts = Vec::with_capacity(user_count);
+    for _ in 0..user_count {
+        edit_counts.push(index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?);
+    }
+    Ok(edit_counts)
+}
+
+fn read_namespaces(index: &mut dyn Index, header: &IndexHeader) -> Result<BTreeMap<i32, String>, Error> {
+    let mut namespaces = BTreeMap::new();
+    if let Some(section) = header.section(SF_SECTION_NAMESPACES) {
+        index.seek(SeekFrom::Start(section.offset)).map_err(Error::from_index)?;
+        let mut section_reader = index.take(section.length);
+        while let Ok(key) = section_reader.read_i32::<byteorder::LittleEndian>() {
+            let mut name = String::new();
+            section_reader.read_line(&mut name).map_err(Error::from_index)?;
+            name.pop();
+            namespaces.insert(key, name);
+        }
+    }
+    Ok(namespaces)
+}
+
+// Read the page record at this offset, and return the namespace of the page
+// along with its number of distinct editors, if the index knows it
+fn read_page(index: &mut dyn Index, header: &IndexHeader, page_offset: u64, page_name: &mut String) -> Result<(i32, Option<u32>), Error> {
+    index.seek(SeekFrom::Start(page_offset)).map_err(Error::from_index)?;
+    let namespace = if header.version > SF_VERSION_WITHOUT_NAMESPACES {
+        index.read_i32::<byteorder::LittleEndian>().map_err(Error::from_index)?
+    } else {
+        0
+    };
+    let editor_count = if header.version > SF_VERSION_WITHOUT_EDITOR_COUNTS {
+        Some(index.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?)
+    } else {
+        None
+    };
+    index.read_line(page_name).map_err(Error::from_index)?;
+    page_name.pop();
+    Ok((namespace, editor_count))
+}
+
+// Restrictions of a filter on pages rather than on edits
+struct PageFilter {
+    included: HashSet<i32>,
+    excluded: HashSet<i32>,
+    max_editor_count: Option<u32>,
+}
+
+impl PageFilter {
+    fn accepts(&self, namespace: i32, editor_count: Option<u32>) -> bool {
+        (self.included.is_empty() || self.included.contains(&namespace)) &&
+        !self.excluded.contains(&namespace) &&
+        self.max_editor_count.is_none_or(|max_editor_count| editor_count.is_none_or(|editor_count| editor_count <= max_editor_count))
+    }
+}
+
+fn resolve_namespace(namespaces: &BTreeMap<i32, String>, namespace: &str) -> Option<i32> {
+    let namespace = namespace.replace("_", " ");
+    let namespace = namespace.trim();
+    match namespace.parse() {
+        Ok(key) => Some(key),
+        Err(_) => namespaces.iter()
+            .find(|(_, name)| name.to_lowercase() == namespace.to_lowercase())
+            .map(|(key, _)| *key),
+    }
+}
+
+fn read_metadata(index: &mut dyn Index, header: &IndexHeader) -> Result<Option<Metadata>, Error> {
+    let section = match header.section(SF_SECTION_METADATA) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    index.seek(SeekFrom::Start(section.offset)).map_err(Error::from_index)?;
+    let mut metadata = Metadata::default();
+    for line in index.take(section.length).lines() {
+        let line = line.map_err(Error::from_index)?;
+        // unknown fields have been added by newer versions of socksfinder
+        match line.split_once('=') {
+            Some(("dbname", value)) => metadata.dbname = value.to_string(),
+            Some(("base_url", value)) => metadata.base_url = value.to_string(),
+            Some(("dump_time", value)) => metadata.dump_time = value.parse().unwrap_or(0),
+            Some(("build_time", value)) => metadata.build_time = value.parse().unwrap_or(0),
+            Some(("socksfinder_version", value)) => metadata.socksfinder_version = value.to_string(),
+            Some(("page_count", value)) => metadata.page_count = value.parse().unwrap_or(0),
+            Some(("user_count", value)) => metadata.user_count = value.parse().unwrap_or(0),
+            Some(("revision_count", value)) => metadata.revision_count = value.parse().unwrap_or(0),
+            _ => (),
+        }
+    }
+    Ok(Some(metadata))
+}
+
+/// Read the metadata of an index, if it has been built by a version of
+/// socksfinder recent enough to record them
+pub fn metadata(index: &mut dyn Index) -> Result<Option<Metadata>, Error> {
+    let header = read_index_header(index)?;
+    read_metadata(index, &header)
 }
 
-#[allow(non_camel_case_types)]
-#[derive(Clone)]
-#[derive(Copy)]
-#[derive(Deserialize)]
-#[derive(PartialEq)]
-pub enum Order {
-    alphabetical,
-    count_decreasing,
-    count_increasing,
-    none,
+// Walking a corrupt FST can panic, so its checksum has to be verified first
+// (FSTs of indexes in older format versions may have been built before fst
+// 0.4 and have none, in which case they are trusted)
+fn check_fst<D: AsRef<[u8]>>(header: &IndexHeader, fst: fst::Map<D>) -> Result<fst::Map<D>, Error> {
+    match fst.as_fst().verify() {
+        Ok(()) => Ok(fst),
+        Err(fst::Error::Fst(fst::raw::Error::ChecksumMissing)) if header.version < SF_VERSION => Ok(fst),
+        Err(error) => Err(error.into()),
+    }
 }
 
-const SF_IDENTIFIER_LENGTH: usize = 2;
-const SF_IDENTIFIER: [u8; SF_IDENTIFIER_LENGTH] = [0x53, 0x46];
-const SF_VERSION: u16 = 0;
+fn read_fst(index: &mut dyn Index, header: &IndexHeader) -> Result<fst::Map<Vec<u8>>, Error> {
+    let fst_section = header.fst_section();
+    index.seek(SeekFrom::Start(fst_section.offset)).map_err(Error::from_index)?;
+    let mut fst_reader = index.take(fst_section.length);
+    let mut fst_bytes = vec![];
+    fst_reader.read_to_end(&mut fst_bytes).map_err(Error::from_index)?;
+    check_fst(header, fst::Map::new(fst_bytes)?)
+}
 
-pub trait Index: BufRead + Seek {}
-impl<T: BufRead + Seek> Index for T {}
+// Check that postings fit before the FST, which follows them, before
+// allocating anything for them
+fn check_postings(header: &IndexHeader, offset: u64, edit_count: u64, posting_length: u64) -> Result<(), Error> {
+    let fst_offset = header.fst_section().offset;
+    let end_offset = edit_count.checked_mul(posting_length).and_then(|length| length.checked_add(offset));
+    if end_offset.is_none_or(|end_offset| end_offset > fst_offset) {
+        return Err(Error::Corrupt(format!("{} postings at offset {} overlap the FST at offset {}", edit_count, offset, fst_offset)));
+    }
+    Ok(())
+}
 
-pub fn version() -> &'static str {
-    if env!("CARGO_PKG_VERSION").ends_with("-dev") {
-        concat!(env!("CARGO_PKG_VERSION"), "+", env!("VERGEN_GIT_SHA_SHORT"))
-    } else {
-        env!("CARGO_PKG_VERSION")
+// Read the postings of a user from an index in the current format version
+fn read_postings(index: &mut dyn Index, header: &IndexHeader, value: u64) -> Result<Vec<Posting>, Error> {
+    index.seek(SeekFrom::Start(value)).map_err(Error::from_index)?;
+    let edit_count = index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+    check_postings(header, value + 8, edit_count, 16)?;
+    let mut postings = Vec::with_capacity(edit_count as usize);
+    for _ in 0..edit_count {
+        postings.push(Posting {
+            page_offset: index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+            first_edit: index.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+            last_edit: index.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+        });
     }
+    Ok(postings)
 }
 
-pub fn build(reader: &mut dyn BufRead, writer: &mut dyn Write) -> Result<(), ()> {
-    writer.write_all(&SF_IDENTIFIER).unwrap();
-    writer.write_u16::<byteorder::LittleEndian>(SF_VERSION).unwrap();
-    let mut current_offset = 4u32;
-    let mut user_page_offsets = BTreeMap::new();
-    let mut xml_reader = Reader::from_reader(reader);
-    let mut buffer = Vec::new();
-    let mut current_tag = Tag::Other;
-    let mut previous_page_length = 0usize;
-    loop {
-        match xml_reader.read_event_into(&mut buffer) {
-            Ok(Event::Start(ref event)) => {
-                match event.name().as_ref() {
-                    b"title" => current_tag = Tag::Title,
-                    b"ip" => current_tag = Tag::UserName,
-                    b"username" => current_tag = Tag::UserName,
-                    _ => current_tag = Tag::Other,
-                }
-            },
-            Ok(Event::End(_)) => current_tag = Tag::Other,
-            Ok(Event::Text(ref event)) => {
-                let escaped_event = event.unescape();
-                match current_tag {
-                    Tag::Title => {
-                        match escaped_event {
-                            Ok(ref buffer) => {
-                                current_offset += previous_page_length as u32;
-                                writer.write_all(buffer.as_bytes()).unwrap();
-                                writer.write_u8(0xA).unwrap();
-                                previous_page_length = buffer.len() + 1;
-                            }
-                            Err(_) => (), // ignore encoding error in the dump
-                        }
-                    }
-                    Tag::UserName => {
-                        match escaped_event {
-                            Ok(ref buffer) => {
-                                let user = buffer.replace("_", " ");
-                                let page_offsets = user_page_offsets.entry(user.as_bytes().to_vec()).or_insert(Vec::new());
-                                match page_offsets.last() {
-                                    None => page_offsets.push(current_offset),
-                                    Some(last_offset) => {
-                                        if current_offset != *last_offset {
-                                            page_offsets.push(current_offset);
-                                        }
-                                    }
-                                }
-                            },
-                            Err(_) => (), // ignore encoding error in the dump
-                        }
-                    },
-                    Tag::Other => (),
-                }
-            },
-            Err(error) => {
-                eprintln!("socksfinder: XML parsing error at position {}: {:?}", xml_reader.buffer_position(), error);
-                break;
-            },
-            Ok(Event::Eof) => break,
-            _ => (),
+// Read the postings of a user from an index in any format version, only
+// keeping pages for which the span between the first and the last edit of the
+// user overlaps the [since; until] window. Indexes without timestamps give
+// postings spanning all times.
+fn read_user_postings(index: &mut dyn Index, header: &IndexHeader, value: u64, filter: &Filter) -> Result<Vec<Posting>, Error> {
+    let mut postings;
+    if header.version == SF_VERSION_32_BITS {
+        let edit_count = value & 0xFF_FF_FF_FF;
+        check_postings(header, value >> 32, edit_count, 4)?;
+        index.seek(SeekFrom::Start(value >> 32)).map_err(Error::from_index)?;
+        postings = Vec::with_capacity(edit_count as usize);
+        for _ in 0..edit_count {
+            postings.push(Posting {
+                page_offset: index.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)? as u64,
+                first_edit: u32::MIN,
+                last_edit: u32::MAX,
+            });
         }
-        buffer.clear();
-    }
-    current_offset += previous_page_length as u32;
-    for page_offsets in user_page_offsets.values_mut() {
-        let edit_count = page_offsets.len() as u32;
-        let page_offsets_offset = current_offset;
-        for page_offset in page_offsets.iter() {
-            writer.write_u32::<byteorder::LittleEndian>(*page_offset).unwrap();
+    } else if header.version == SF_VERSION_WITHOUT_TIMESTAMPS {
+        index.seek(SeekFrom::Start(value)).map_err(Error::from_index)?;
+        let edit_count = index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        check_postings(header, value + 8, edit_count, 8)?;
+        postings = Vec::with_capacity(edit_count as usize);
+        for _ in 0..edit_count {
+            postings.push(Posting {
+                page_offset: index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?,
+                first_edit: u32::MIN,
+                last_edit: u32::MAX,
+            });
         }
-        current_offset += (page_offsets.len() as u32) * 4;
-        page_offsets.clear();
-        page_offsets.push(page_offsets_offset);
-        page_offsets.push(edit_count);
+    } else {
+        postings = read_postings(index, header, value)?;
+        postings.retain(|posting| posting.last_edit >= filter.since.unwrap_or(u32::MIN) &&
+                                  posting.first_edit <= filter.until.unwrap_or(u32::MAX));
     }
-    let fst_offset = current_offset;
-    let mut fst_builder = MapBuilder::new(writer).unwrap();
-    for (user, page_offsets) in user_page_offsets {
-        fst_builder.insert(user, (page_offsets[0] as u64) << 32 | (page_offsets[1] as u64)).unwrap();
+    Ok(postings)
+}
+
+fn read_page_offsets(index: &mut dyn Index, header: &IndexHeader, value: u64, filter: &Filter) -> Result<Vec<u64>, Error> {
+    Ok(read_user_postings(index, header, value, filter)?.into_iter()
+        .map(|posting| posting.page_offset)
+        .collect())
+}
+
+fn read_edit_count(index: &mut dyn Index, header: &IndexHeader, value: u64) -> Result<u64, Error> {
+    if header.version == SF_VERSION_32_BITS {
+        Ok(value & 0xFF_FF_FF_FF)
+    } else {
+        index.seek(SeekFrom::Start(value)).map_err(Error::from_index)?;
+        Ok(index.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?)
     }
-    let writer = fst_builder.into_inner().unwrap();
-    writer.write_u32::<byteorder::LittleEndian>(fst_offset).unwrap();
-    Ok(())
 }
 
-struct InvertedList<'a> {
-    user: &'a String,
-    position: usize,
-    page_offsets: Vec<u32>,
+fn page_offsets_offset(header: &IndexHeader, value: u64) -> u64 {
+    if header.version == SF_VERSION_32_BITS {
+        value >> 32
+    } else {
+        value
+    }
 }
 
-struct Page {
-    page_name: String,
-    editor_count: usize,
-    editor_names: String,
+// Offset of the end of the page names, where the postings of the first user
+// start
+fn page_names_end_offset<D: AsRef<[u8]>>(header: &IndexHeader, fst: &fst::Map<D>) -> Result<u64, Error> {
+    let mut values = fst.values();
+    let mut page_names_end_offset = header.fst_section().offset;
+    while let Some(value) = values.next() {
+        page_names_end_offset = page_names_end_offset.min(page_offsets_offset(header, value));
+    }
+    if page_names_end_offset < SF_HEADER_LENGTH {
+        return Err(Error::Corrupt(format!("postings start at offset {}, inside the header", page_names_end_offset)));
+    }
+    Ok(page_names_end_offset)
 }
 
-fn read_index_header(index: &mut dyn Index) -> Result<(u64, u64), ()> {
-    let mut identifier_bytes = [0u8; SF_IDENTIFIER_LENGTH];
-    match index.read(&mut identifier_bytes) {
-        Ok(length) => {
-            if length != SF_IDENTIFIER_LENGTH ||
-               identifier_bytes != SF_IDENTIFIER {
-                   eprintln!("socksfinder: not a socksfinder index");
-                   return Err(())
+// Count the pages of each namespace (all of them in the main namespace for
+// indexes without namespaces) by number of editors (if the index knows it)
+fn read_page_counts(index: &mut dyn Index, header: &IndexHeader, page_names_end_offset: u64) -> Result<BTreeMap<(i32, Option<u32>), u64>, Error> {
+    let mut page_counts = BTreeMap::new();
+    index.seek(SeekFrom::Start(SF_HEADER_LENGTH)).map_err(Error::from_index)?;
+    let mut page_reader = index.take(page_names_end_offset - SF_HEADER_LENGTH);
+    let mut page_name = vec![];
+    loop {
+        let namespace = if header.version > SF_VERSION_WITHOUT_NAMESPACES {
+            match page_reader.read_i32::<byteorder::LittleEndian>() {
+                Ok(namespace) => namespace,
+                Err(_) => break,
             }
-        },
-        Err(_) => {
-            eprintln!("socksfinder: not a socksfinder index");
-            return Err(())
-        }
-    }
-    match index.read_u16::<byteorder::LittleEndian>() {
-        Ok(index_version) => {
-            if index_version != SF_VERSION {
-                eprintln!("socksfinder: can't read index in format version {}, only format version {} is supported by socksfinder v{}", index_version, SF_VERSION, version());
-                return Err(())
+        } else {
+            0
+        };
+        let editor_count = if header.version > SF_VERSION_WITHOUT_EDITOR_COUNTS {
+            match page_reader.read_u32::<byteorder::LittleEndian>() {
+                Ok(editor_count) => Some(editor_count),
+                Err(_) => break,
             }
-        },
-        Err(_) => {
-            eprintln!("socksfinder: unable to read index format version number");
-            return Err(())
+        } else {
+            None
+        };
+        if page_reader.read_until(0xA, &mut page_name).map_err(Error::from_index)? == 0 {
+            break;
         }
+        page_name.clear();
+        *page_counts.entry((namespace, editor_count)).or_insert(0) += 1;
     }
-    let fst_end_offset = index.seek(SeekFrom::End(-4)).unwrap();
-    let fst_start_offset = index.read_u32::<byteorder::LittleEndian>().unwrap();
-    Ok((fst_start_offset as u64, fst_end_offset))
+    Ok(page_counts)
 }
 
-pub fn query(index: &mut dyn Index, writer: &mut dyn Write, users: &Vec<String>, threshold: usize, order: Order, show_cooccurrences: bool, try_format: bool) -> Result<(), ()> {
-    let users: &Vec<String> = {
-        let mut unique_users = HashSet::with_capacity(users.len());
-        for user in users {
-            unique_users.insert(user.replace("_", " ").trim().to_string().clone());
-        }
-        &unique_users.into_iter().collect()
-    };
-    let threshold = if show_cooccurrences {
-        0
-    } else if threshold == 0 {
-        users.len()
-    } else {
-        threshold
-    };
-    let (fst_start_offset, fst_end_offset) = read_index_header(index)?;
-    index.seek(SeekFrom::Start(fst_start_offset)).unwrap();
-    let mut fst_reader = index.take(fst_end_offset - fst_start_offset);
-    let mut fst_bytes = vec![];
-    fst_reader.read_to_end(&mut fst_bytes).unwrap();
-    let fst = fst::Map::new(fst_bytes).unwrap();
-    let mut lists = vec![];
-    let mut min_page_offsets = HashSet::with_capacity(users.len());
-    let mut error = false;
-    for user in users {
-        match fst.get(&user) {
-            None => {
-                error = true;
-                match writeln!(writer, "Error: User '{}' does not exist or has no edits", user) {
-                    Ok(()) => (),
-                    Err(_) => (), // ignore output error, but give up
-                }
-            },
-            Some(value) => {
-                let edit_count = value & 0xFF_FF_FF_FF;
-                let page_offsets_offset = value >> 32;
-                index.seek(SeekFrom::Start(page_offsets_offset)).unwrap();
-                let mut page_offsets = Vec::<u32>::with_capacity(edit_count as usize);
-                for _ in 0..edit_count {
-                    page_offsets.push(index.read_u32::<byteorder::LittleEndian>().unwrap());
-                }
-                lists.push(InvertedList {
-                    user,
-                    position: 0,
-                    page_offsets
-                });
-                min_page_offsets.insert(lists.last().unwrap().page_offsets[0]);
-            }
-        }
+/// Write the pages modified by several users, or matching the expression if
+/// any (or the co-occurrences matrix, with the similarity of the users
+/// according to the metric, and without the users with no pair more
+/// significant than max_p_value; graphs only link the users with at least
+/// min_shared_pages pages in common) in the given format. Pages of the known
+/// users are written even if some of the users are unknown, in which case an
+/// error is returned afterwards.
+#[allow(clippy::too_many_arguments)]
+pub fn query(index: &IndexReader, writer: &mut dyn Write, users: &[String], threshold: usize, expression: Option<&Expression>, order: Order, filter: &Filter, show_cooccurrences: bool, metric: Metric, max_p_value: Option<f64>, min_shared_pages: u64, format: Format, try_format: bool) -> Result<(), Error> {
+    let unknown_users;
+    if show_cooccurrences && expression.is_some() {
+        return Err(Error::InvalidQuery("expressions can only be used to search pages".to_string()));
     }
-    let mut heap = BinaryHeap::with_capacity(min_page_offsets.len());
-    for min_page_offset in min_page_offsets {
-        heap.push(Reverse(min_page_offset));
+    if !show_cooccurrences {
+        check_list_format(format)?;
     }
-    let mut page_name = String::new();
-    let mut editors = Vec::with_capacity(users.len());
-    let mut cooccurrences = if show_cooccurrences {
-        // TODO only half of the matrix is actually necessary
-        HashMap::with_capacity(users.len() * users.len())
+    if show_cooccurrences {
+        let mut matrix = index.cooccurrences(users, filter)?;
+        if let Some(max_p_value) = max_p_value {
+            matrix.retain_significant(max_p_value);
+        }
+        matrix.sort(order, metric);
+        match output::write_matrix(writer, format, &matrix, metric, min_shared_pages, index.namespaces(), try_format) {
+            Ok(()) => (),
+            Err(_) => (), // ignore output error
+        }
+        unknown_users = matrix.unknown_users().to_vec();
     } else {
-        HashMap::new()
-    };
-    let mut list_count = lists.len();
-    let mut pages = Vec::new();
-    while !heap.is_empty() &&
-          list_count >= threshold {
-        let Reverse(current_page_offset) = heap.pop().unwrap();
-        let mut editor_count = 0;
-        for list in &mut lists {
-            if list.page_offsets[list.position] == current_page_offset {
-                editor_count += 1;
-                editors.push(list.user);
-                if list.position < list.page_offsets.len() - 1 {
-                    list.position += 1;
-                    heap.push(Reverse(list.page_offsets[list.position]));
-                } else {
-                    list_count -= 1;
-                }
-            }
+        if order == Order::rarity && index.header.version <= SF_VERSION_WITHOUT_EDITOR_COUNTS {
+            return Err(Error::OutdatedIndex(format!("index in format version {} has no page editor counts, rebuild it to order pages by rarity", index.header.version)));
         }
-        if show_cooccurrences && editors.len() > 1 {
-            for first_editor in &editors {
-                for second_editor in &editors {
-                    cooccurrences.entry((first_editor.clone(), second_editor.clone())).and_modify(|value| { *value += 1 }).or_insert(1);
+        let mut pages = match expression {
+            Some(expression) => index.pages_matching(expression, filter)?,
+            None => index.pages(users, threshold, filter)?,
+        };
+        unknown_users = pages.unknown_users().to_vec();
+        // ignore output errors, but give up
+        let mut page_writer = output::PageWriter::new(writer, format, index.namespaces()).ok();
+        let mut write_page = |page: &PageMatch| {
+            if let Some(writer) = &mut page_writer {
+                match writer.write(page) {
+                    Ok(()) => (),
+                    Err(_) => page_writer = None,
                 }
             }
-        } else if editor_count >= threshold {
-            index.seek(SeekFrom::Start(current_page_offset as u64)).unwrap();
-            index.read_line(&mut page_name).unwrap();
-            page_name.pop();
-            let mut editor_names = String::with_capacity(editors.len() * 20);
-            for editor in &editors {
-                editor_names.push_str(editor);
-                editor_names.push_str(", ");
-            }
-            editor_names.truncate(editor_names.len() - 2);
-            match order {
-                Order::none => {
-                    match writeln!(writer, "{}: {} ({})", page_name, editor_count, editor_names) {
-                        Ok(()) => (),
-                        Err(_) => (), // ignore output error, but give up
-                    }
-                },
-                _ => pages.push(Page {
-                    page_name: page_name.clone(),
-                    editor_count,
-                    editor_names
-                }),
-            }
-            page_name.clear();
-        }
-        editors.clear();
-    }
-    if show_cooccurrences {
-        let mut sorted_users = users.clone();
-        if order != Order::none {
-            sorted_users.sort_unstable_by(|first_user, second_user| {
-                if order == Order::alphabetical {
-                    first_user.cmp(second_user)
-                } else {
-                    let total = |user: &String| {
-                        let mut sum = 0;
-                        for other_user in users {
-                            if other_user != user {
-                                sum += cooccurrences.get(&(&user.clone(), &other_user.clone())).unwrap_or(&0);
-                            }
-                        }
-                        sum
-                    };
-                    if order == Order::count_decreasing {
-                        total(second_user).cmp(&total(first_user))
-                    } else {
-                        total(first_user).cmp(&total(second_user))
-                    }
+        };
+        match order {
+            Order::none => {
+                for page in &mut pages {
+                    write_page(&page?);
                 }
-            });
-        }
-        let mut table = Table::new();
-        let mut row = vec![Cell::new("")];
-        for user in &sorted_users {
-            row.push(Cell::new(user).style_spec("b"));
-        }
-        table.add_row(Row::new(row));
-        for row_user in &sorted_users {
-            let mut row = vec![Cell::new(row_user).style_spec("b")];
-            for cell_user in &sorted_users {
-                if row_user == cell_user {
-                    row.push(Cell::new(""));
-                } else {
-                    row.push(Cell::new(&(cooccurrences.get(&(&row_user.clone(), &cell_user.clone())).unwrap_or(&0)).to_string()));
+            },
+            _ => {
+                let mut pages = pages.collect::<Result<Vec<_>, _>>()?;
+                sort_pages(&mut pages, order);
+                for page in &pages {
+                    write_page(page);
                 }
             }
-            table.add_row(Row::new(row));
         }
-        if try_format {
-            table.printstd();
-        } else {
-            match table.print(writer) {
-                Ok(_) => (),
+        if let Some(page_writer) = page_writer {
+            match page_writer.finish() {
+                Ok(()) => (),
                 Err(_) => (), // ignore output error
             }
         }
+    }
+    if unknown_users.is_empty() {
+        Ok(())
     } else {
-        match order {
-            Order::none => (),
-            _ => {
-                pages.sort_unstable_by(|first_page, second_page| {
-                    match order {
-                        Order::alphabetical => first_page.page_name.cmp(&second_page.page_name),
-                        Order::count_decreasing => second_page.editor_count.cmp(&first_page.editor_count),
-                        Order::count_increasing => first_page.editor_count.cmp(&second_page.editor_count),
-                        _ => unreachable!()
-                    }
-                });
-                for page in pages {
-                    match writeln!(writer, "{}: {} ({})", page.page_name, page.editor_count, page.editor_names) {
-                        Ok(()) => (),
-                        Err(_) => break, // ignore output error, but give up
-                    }
-                }
-            }
-        }
+        Err(Error::UnknownUsers(unknown_users))
+    }
+}
+
+/// Write the communities of users who have modified more pages together than
+/// with the other users, according to the metric (and without the users
+/// with no pair more significant than max_p_value), in the given format
+/// (see [`CooccurrenceMatrix::communities`]). Communities of the known users
+/// are written even if some of the users are unknown, in which case an error
+/// is returned afterwards.
+pub fn communities(index: &IndexReader, writer: &mut dyn Write, users: &[String], filter: &Filter, metric: Metric, max_p_value: Option<f64>, format: Format) -> Result<(), Error> {
+    check_list_format(format)?;
+    let mut matrix = index.cooccurrences(users, filter)?;
+    if let Some(max_p_value) = max_p_value {
+        matrix.retain_significant(max_p_value);
+    }
+    let communities = matrix.communities(metric);
+    match output::write_communities(writer, format, &communities, index.namespaces()) {
+        Ok(()) => (),
+        Err(_) => (), // ignore output error
+    }
+    if communities.unknown_users().is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnknownUsers(communities.unknown_users().to_vec()))
+    }
+}
+
+/// Write the users who have modified at least threshold of the pages (or all
+/// of them if threshold is 0) in the given format. Editors of the known pages
+/// are written even if some of the pages are unknown, in which case an error
+/// is returned afterwards.
+pub fn page(index: &IndexReader, writer: &mut dyn Write, titles: &[String], threshold: usize, order: Order, format: Format) -> Result<(), Error> {
+    check_list_format(format)?;
+    let mut editors = index.editors(titles, threshold)?;
+    editors.sort(order);
+    match output::write_editors(writer, format, editors.editors(), index.namespaces()) {
+        Ok(()) => (),
+        Err(_) => (), // ignore output error
+    }
+    if editors.unknown_pages().is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnknownPages(editors.unknown_pages().to_vec()))
     }
-    if error {
-        Err(())
+}
+
+/// Write the top users most similar to the user (all of them if top is 0),
+/// according to the metric, in the given format. The excluded users (eg.
+/// bots) are skipped. With estimate, the users are the top candidates found
+/// by signature, whose similarity is then computed exactly.
+#[allow(clippy::too_many_arguments)]
+pub fn similar(index: &IndexReader, writer: &mut dyn Write, user: &str, filter: &Filter, excluded_users: &[String], metric: Metric, top: usize, estimate: bool, format: Format) -> Result<(), Error> {
+    check_list_format(format)?;
+    let mut similar_users = vec![];
+    if estimate {
+        let candidates = match index.similar_candidates(user, excluded_users, top)? {
+            Some(candidates) => candidates,
+            None => return Err(Error::UnknownUsers(vec![user.to_string()])),
+        };
+        similar_users = index.similar_estimated_users(user, candidates, filter, metric)?;
     } else {
+        similar_users = match index.similar_users(user, filter, excluded_users, metric)? {
+            Some(similar_users) => similar_users,
+            None => return Err(Error::UnknownUsers(vec![user.to_string()])),
+        };
+        if top > 0 {
+            similar_users.truncate(top);
+        }
+    }
+    match output::write_similar_users(writer, format, &similar_users, metric, index.namespaces()) {
+        Ok(()) => (),
+        Err(_) => (), // ignore output error
+    }
+    Ok(())
+}
+
+/// Write the users who have modified pages of the seeds in the given format,
+/// hop after hop (see [`IndexReader::expand`]). Users found from the known
+/// seeds are written even if some of the seeds are unknown, in which case an
+/// error is returned afterwards.
+#[allow(clippy::too_many_arguments)]
+pub fn expand(index: &IndexReader, writer: &mut dyn Write, seeds: &[String], filter: &Filter, excluded_users: &[String], hops: usize, decay: f64, top: usize, format: Format) -> Result<(), Error> {
+    check_list_format(format)?;
+    if hops == 0 {
+        return Err(Error::InvalidQuery("the number of hops must be at least 1".to_string()));
+    }
+    if !(0. ..=1.).contains(&decay) {
+        return Err(Error::InvalidQuery(format!("decay {} isn't between 0 and 1", decay)));
+    }
+    let expansion = index.expand(seeds, filter, excluded_users, hops, decay, top)?;
+    match output::write_expanded_users(writer, format, expansion.users(), index.namespaces()) {
+        Ok(()) => (),
+        Err(_) => (), // ignore output error
+    }
+    if expansion.unknown_users().is_empty() {
         Ok(())
+    } else {
+        Err(Error::UnknownUsers(expansion.unknown_users().to_vec()))
+    }
+}
+
+/// Write the pages on which revisions of the user have directly followed
+/// revisions of the followed user in the given format, with the number of
+/// times and the delays between their revisions.
+pub fn follows(index: &IndexReader, writer: &mut dyn Write, user: &str, followed_user: &str, order: Order, filter: &Filter, format: Format) -> Result<(), Error> {
+    check_list_format(format)?;
+    let mut follows = index.follows(user, followed_user, filter)?;
+    if !follows.unknown_users().is_empty() {
+        return Err(Error::UnknownUsers(follows.unknown_users().to_vec()));
+    }
+    follows.sort(order);
+    match output::write_followed_pages(writer, format, follows.pages()) {
+        Ok(()) => (),
+        Err(_) => (), // ignore output error
     }
+    Ok(())
 }
 
 struct RamIndex {
     name: String,
-    data: Vec<u8>,
+    // shared with the queries being answered, so that the index can be
+    // reloaded while they are running
+    reader: Option<Arc<IndexReader>>,
+}
+
+impl RamIndex {
+    fn metadata(&self) -> Option<&Metadata> {
+        self.reader.as_ref()?.metadata()
+    }
+
+    fn description(&self) -> String {
+        match self.metadata() {
+            Some(metadata) => metadata.description(),
+            None => self.name.clone(),
+        }
+    }
 }
 
 struct AppState {
@@ -431,11 +1822,19 @@ struct AppState {
     ram_index: Mutex<RamIndex>,
 }
 
+mod filters {
+    pub fn timestamp(timestamp: &u32) -> askama::Result<String> {
+        Ok(crate::format_timestamp(*timestamp))
+    }
+}
+
 #[derive(Template)]
 #[template(path = "index.htm")]
 struct IndexTemplate<'a> {
     version: &'a str,
     index: String,
+    metadata: Option<Metadata>,
+    namespaces: Vec<(i32, String)>,
 }
 
 #[get("/")]
@@ -443,7 +1842,15 @@ async fn serve_index(data: Data<AppState>) -> impl Responder {
     let ram_index = data.ram_index.lock().unwrap();
     IndexTemplate {
         version: version(),
-        index: ram_index.name.clone(),
+        index: ram_index.description(),
+        metadata: ram_index.metadata().cloned(),
+        namespaces: match &ram_index.reader {
+            Some(reader) => reader.namespaces.iter()
+                .filter(|(key, _)| **key >= 0)
+                .map(|(key, name)| (*key, if name.is_empty() { "(main)".to_string() } else { name.clone() }))
+                .collect(),
+            None => vec![],
+        },
     }
 }
 
@@ -483,61 +1890,314 @@ async fn serve_favicon(_data: Data<AppState>) -> WebResult<NamedFile> {
 #[derive(Deserialize)]
 struct QueryRequest {
     cooccurrences: Option<bool>,
+    communities: Option<bool>,
+    #[serde(default)]
     users: String,
+    expression: Option<String>,
     threshold: Option<usize>,
     order: Option<Order>,
+    since: Option<String>,
+    until: Option<String>,
+    namespaces: Option<String>,
+    exclude_namespaces: Option<String>,
+    max_page_editors: Option<String>,
+    metric: Option<Metric>,
+    max_p_value: Option<String>,
+    min_shared_pages: Option<String>,
+    format: Option<Format>,
+}
+
+fn split_list_parameter(list: &Option<String>) -> Vec<String> {
+    match list.as_deref() {
+        None | Some("") => vec![],
+        Some(list) => list.split(',').map(|item| item.to_string()).collect(),
+    }
+}
+
+fn parse_date_parameter(date: &Option<String>, end_of_day: bool) -> Result<Option<u32>, String> {
+    match date.as_deref() {
+        None | Some("") => Ok(None),
+        Some(date) => parse_date(date, end_of_day)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid date: '{}' (expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ)\n", date)),
+    }
+}
+
+fn parse_number_parameter<T: std::str::FromStr>(number: &Option<String>, name: &str, expected: &str) -> Result<Option<T>, String> {
+    match number.as_deref() {
+        None | Some("") => Ok(None),
+        Some(number) => number.parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid {}: '{}' (expected {})\n", name, number, expected)),
+    }
+}
+
+// Filter shared by the requests searching edits
+fn parse_filter_parameters(since: &Option<String>, until: &Option<String>, namespaces: &Option<String>, exclude_namespaces: &Option<String>, max_page_editors: &Option<String>) -> Result<Filter, String> {
+    Ok(Filter {
+        since: parse_date_parameter(since, false)?,
+        until: parse_date_parameter(until, true)?,
+        namespaces: split_list_parameter(namespaces),
+        excluded_namespaces: split_list_parameter(exclude_namespaces),
+        max_page_editors: parse_number_parameter(max_page_editors, "editor count", "a positive integer")?,
+    })
+}
+
+fn content_type(format: Format) -> Mime {
+    match format {
+        Format::text | Format::wikitext => TEXT_PLAIN_UTF_8,
+        Format::json => APPLICATION_JSON,
+        Format::jsonl => "application/jsonl".parse().unwrap(),
+        Format::csv => TEXT_CSV_UTF_8,
+        Format::tsv => "text/tab-separated-values; charset=utf-8".parse().unwrap(),
+        Format::dot => "text/vnd.graphviz; charset=utf-8".parse().unwrap(),
+        Format::graphml => "application/graphml+xml; charset=utf-8".parse().unwrap(),
+        Format::gexf => "application/gexf+xml; charset=utf-8".parse().unwrap(),
+    }
 }
 
 #[get("/query")]
 async fn serve_query(query_request: Query<QueryRequest>, data: Data<AppState>) -> impl Responder {
-    let users = query_request.users.split(',').map(|user| user.to_string()).collect();
-    let ram_index = data.ram_index.lock().unwrap();
-    if ram_index.data.is_empty() {
-        return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n");
-    }
-    let mut cursor = Cursor::new(&*ram_index.data);
+    let users: Vec<String> = query_request.users.split(',').map(|user| user.to_string()).collect();
+    let filter = match parse_filter_parameters(&query_request.since, &query_request.until, &query_request.namespaces, &query_request.exclude_namespaces, &query_request.max_page_editors) {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let max_p_value = match parse_number_parameter(&query_request.max_p_value, "p-value", "a number between 0 and 1") {
+        Ok(max_p_value) => max_p_value,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let min_shared_pages = match parse_number_parameter(&query_request.min_shared_pages, "number of pages", "a positive integer") {
+        Ok(min_shared_pages) => min_shared_pages,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let expression = match query_request.expression.as_deref() {
+        None | Some("") => None,
+        Some(expression) => match expression.parse::<Expression>() {
+            Ok(expression) => Some(expression),
+            Err(error) => return HttpResponse::build(error.status_code())
+                .insert_header(ContentType(TEXT_PLAIN_UTF_8))
+                .body(format!("Error: {}\n", error)),
+        },
+    };
+    let reader = match &data.ram_index.lock().unwrap().reader {
+        Some(reader) => reader.clone(),
+        None => return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n"),
+    };
+    let format = query_request.format.unwrap_or(Format::text);
     let mut response = vec![];
-    match query(&mut cursor, &mut response, &users, query_request.threshold.unwrap_or(0), query_request.order.unwrap_or(Order::none), query_request.cooccurrences.unwrap_or(false), false) {
-        Ok(()) => (),
-        Err(()) => (),
+    let result = if query_request.communities.unwrap_or(false) {
+        if expression.is_some() {
+            Err(Error::InvalidQuery("expressions can only be used to search pages".to_string()))
+        } else {
+            communities(&reader, &mut response, &users, &filter, query_request.metric.unwrap_or(Metric::count), max_p_value, format)
+        }
+    } else {
+        query(&reader, &mut response, &users, query_request.threshold.unwrap_or(0), expression.as_ref(), query_request.order.unwrap_or(Order::none), &filter, query_request.cooccurrences.unwrap_or(false), query_request.metric.unwrap_or(Metric::count), max_p_value, min_shared_pages.unwrap_or(1), format, false)
+    };
+    results_response(result, response, format)
+}
+
+#[derive(Deserialize)]
+struct PageRequest {
+    pages: String,
+    threshold: Option<usize>,
+    order: Option<Order>,
+    format: Option<Format>,
+}
+
+#[get("/page")]
+async fn serve_page(page_request: Query<PageRequest>, data: Data<AppState>) -> impl Responder {
+    // titles can contain commas, but neither | nor line breaks
+    let titles: Vec<String> = page_request.pages.split(['|', '\n'])
+        .filter(|title| !title.trim().is_empty())
+        .map(|title| title.to_string())
+        .collect();
+    let reader = match &data.ram_index.lock().unwrap().reader {
+        Some(reader) => reader.clone(),
+        None => return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n"),
+    };
+    let format = page_request.format.unwrap_or(Format::text);
+    let mut response = vec![];
+    let result = page(&reader, &mut response, &titles, page_request.threshold.unwrap_or(1), page_request.order.unwrap_or(Order::none), format);
+    results_response(result, response, format)
+}
+
+#[derive(Deserialize)]
+struct SimilarRequest {
+    user: String,
+    top: Option<String>,
+    metric: Option<Metric>,
+    exclude_users: Option<String>,
+    estimate: Option<bool>,
+    since: Option<String>,
+    until: Option<String>,
+    namespaces: Option<String>,
+    exclude_namespaces: Option<String>,
+    max_page_editors: Option<String>,
+    format: Option<Format>,
+}
+
+#[get("/similar")]
+async fn serve_similar(similar_request: Query<SimilarRequest>, data: Data<AppState>) -> impl Responder {
+    let filter = match parse_filter_parameters(&similar_request.since, &similar_request.until, &similar_request.namespaces, &similar_request.exclude_namespaces, &similar_request.max_page_editors) {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let top = match parse_number_parameter(&similar_request.top, "number of users", "a positive integer") {
+        Ok(top) => top.unwrap_or(10),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let reader = match &data.ram_index.lock().unwrap().reader {
+        Some(reader) => reader.clone(),
+        None => return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n"),
+    };
+    let format = similar_request.format.unwrap_or(Format::text);
+    let mut response = vec![];
+    let result = similar(&reader, &mut response, &similar_request.user, &filter, &split_list_parameter(&similar_request.exclude_users), similar_request.metric.unwrap_or(Metric::count), top, similar_request.estimate.unwrap_or(false), format);
+    results_response(result, response, format)
+}
+
+#[derive(Deserialize)]
+struct ExpandRequest {
+    users: String,
+    hops: Option<String>,
+    decay: Option<String>,
+    top: Option<String>,
+    exclude_users: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    namespaces: Option<String>,
+    exclude_namespaces: Option<String>,
+    max_page_editors: Option<String>,
+    format: Option<Format>,
+}
+
+#[get("/expand")]
+async fn serve_expand(expand_request: Query<ExpandRequest>, data: Data<AppState>) -> impl Responder {
+    let seeds: Vec<String> = expand_request.users.split(',').map(|user| user.to_string()).collect();
+    let filter = match parse_filter_parameters(&expand_request.since, &expand_request.until, &expand_request.namespaces, &expand_request.exclude_namespaces, &expand_request.max_page_editors) {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let hops = match parse_number_parameter(&expand_request.hops, "number of hops", "a positive integer") {
+        Ok(hops) => hops.unwrap_or(1),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let decay = match parse_number_parameter(&expand_request.decay, "decay", "a number between 0 and 1") {
+        Ok(decay) => decay.unwrap_or(0.5),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let top = match parse_number_parameter(&expand_request.top, "number of users", "a positive integer") {
+        Ok(top) => top.unwrap_or(10),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let reader = match &data.ram_index.lock().unwrap().reader {
+        Some(reader) => reader.clone(),
+        None => return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n"),
+    };
+    let format = expand_request.format.unwrap_or(Format::text);
+    let mut response = vec![];
+    let result = expand(&reader, &mut response, &seeds, &filter, &split_list_parameter(&expand_request.exclude_users), hops, decay, top, format);
+    results_response(result, response, format)
+}
+
+#[derive(Deserialize)]
+struct FollowsRequest {
+    user: String,
+    followed_user: String,
+    order: Option<Order>,
+    namespaces: Option<String>,
+    exclude_namespaces: Option<String>,
+    max_page_editors: Option<String>,
+    format: Option<Format>,
+}
+
+#[get("/follows")]
+async fn serve_follows(follows_request: Query<FollowsRequest>, data: Data<AppState>) -> impl Responder {
+    let filter = match parse_filter_parameters(&None, &None, &follows_request.namespaces, &follows_request.exclude_namespaces, &follows_request.max_page_editors) {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let reader = match &data.ram_index.lock().unwrap().reader {
+        Some(reader) => reader.clone(),
+        None => return HttpResponse::ServiceUnavailable().body("Index not yet available (try again later)\n"),
+    };
+    let format = follows_request.format.unwrap_or(Format::text);
+    let mut response = vec![];
+    let result = follows(&reader, &mut response, &follows_request.user, &follows_request.followed_user, follows_request.order.unwrap_or(Order::none), &filter, format);
+    results_response(result, response, format)
+}
+
+// Response to a search request, with the results written so far
+fn results_response(result: Result<(), Error>, response: Vec<u8>, format: Format) -> HttpResponse {
+    match result {
+        Ok(()) => HttpResponse::Ok()
+            .insert_header(ContentType(content_type(format)))
+            .body(response),
+        // errors found before writing anything (eg. invalid query) are reported as text
+        Err(error) if response.is_empty() => HttpResponse::build(error.status_code())
+            .insert_header(ContentType(TEXT_PLAIN_UTF_8))
+            .body(format!("Error: {}\n", error)),
+        // results of the known users (or pages) are still worth showing after
+        // the error, which can't be added to other formats without breaking them
+        Err(error) => HttpResponse::build(error.status_code())
+            .insert_header(ContentType(content_type(format)))
+            .body(if format == Format::text {
+                [format!("Error: {}\n", error).into_bytes(), response].concat()
+            } else {
+                response
+            }),
     }
-    HttpResponse::Ok()
-        .insert_header(ContentType(TEXT_PLAIN_UTF_8))
-        .body(response)
 }
 
 fn get_index_name(path: &str) -> Option<String> {
     Some(Path::new(&path)
         .canonicalize().ok()?
-        .file_stem().unwrap()
-        .to_str().unwrap()
+        .file_stem()?
+        .to_str()?
         .to_string())
 }
 
-fn load_index(data: &Data<AppState>) -> Result<(), &'static str> {
+// Load the index in memory unless it's already loaded, and return whether it
+// has been loaded
+fn load_index(data: &Data<AppState>) -> Result<bool, Error> {
     let mut ram_index_data = vec![];
     let start = Instant::now();
     let name = get_index_name(&data.index_path).unwrap_or_else(|| "unknown".to_string());
-    if name == *data.ram_index.lock().unwrap().name {
-        return Err("Index already up-to-date, no need to reload");
+    let metadata = File::open(&data.index_path).ok()
+        .and_then(|input| metadata(&mut BufReader::new(input)).ok().flatten());
+    {
+        let ram_index = data.ram_index.lock().unwrap();
+        // indexes built before metadata were recorded can only be told apart by their name
+        let up_to_date = match (&metadata, ram_index.metadata()) {
+            (Some(metadata), Some(ram_index_metadata)) => metadata == ram_index_metadata,
+            (None, None) => name == ram_index.name,
+            _ => false,
+        };
+        if up_to_date && ram_index.reader.is_some() {
+            return Ok(false);
+        }
     }
     println!("Loading index...");
-    let input = File::open(&data.index_path);
-    match input {
-        Ok(mut input) => {
-            input.read_to_end(&mut ram_index_data).unwrap();
+    let result = File::open(&data.index_path)
+        .and_then(|mut input| input.read_to_end(&mut ram_index_data))
+        .map_err(Error::from)
+        .and_then(|_| IndexReader::from_bytes(ram_index_data));
+    match result {
+        Ok(reader) => {
             let duration = start.elapsed();
             println!("Index loaded in {:?}", duration);
             let mut app_ram_index = data.ram_index.lock().unwrap();
             *app_ram_index = RamIndex {
-                name: name,
-                data: ram_index_data,
+                name,
+                reader: Some(Arc::new(reader)),
             };
-            Ok(())
+            Ok(true)
         },
         Err(error) => {
-            eprintln!("socksfinder: can't open index: {}: {}", &data.index_path, &error);
-            Err("Unable to open index")
+            eprintln!("socksfinder: can't load index: {}: {}", &data.index_path, &error);
+            Err(error)
         }
     }
 }
@@ -545,11 +2205,14 @@ fn load_index(data: &Data<AppState>) -> Result<(), &'static str> {
 #[get("/reload")]
 async fn serve_reload(data: Data<AppState>) -> impl Responder {
     match load_index(&data) {
-        Ok(()) => {
+        Ok(true) => {
             HttpResponse::Ok().body("Index reloaded\n".to_string())
         }
+        Ok(false) => {
+            HttpResponse::Ok().body("Index already up-to-date, no need to reload\n".to_string())
+        }
         Err(error) => {
-            HttpResponse::InternalServerError().body(format!("{}\n", error))
+            HttpResponse::build(error.status_code()).body(format!("Unable to load index: {}\n", error))
         }
     }
 }
@@ -557,7 +2220,7 @@ async fn serve_reload(data: Data<AppState>) -> impl Responder {
 #[get("/version")]
 async fn serve_version(data: Data<AppState>) -> impl Responder {
     let ram_index = data.ram_index.lock().unwrap();
-    HttpResponse::Ok().body(format!("Running socksfinder v{} ({})\n", version(), &ram_index.name))
+    HttpResponse::Ok().body(format!("Running socksfinder v{} ({})\n", version(), ram_index.description()))
 }
 
 #[actix_web::main]
@@ -566,13 +2229,13 @@ pub async fn serve(index: String, hostname: String, port: u16) -> std::io::Resul
         index_path: index,
         ram_index: Mutex::new(RamIndex {
             name: "no index".to_string(),
-            data: vec![],
+            reader: None,
         }),
     });
     let initial_data = data.clone();
     std::thread::spawn(move || {
         match load_index(&initial_data) {
-            Ok(()) => (),
+            Ok(_) => (),
             Err(_) => (), // index not loaded, but this can be done later using /reload
         }
     });
@@ -586,6 +2249,10 @@ pub async fn serve(index: String, hostname: String, port: u16) -> std::io::Resul
             .service(serve_logo)
             .service(serve_favicon)
             .service(serve_query)
+            .service(serve_page)
+            .service(serve_similar)
+            .service(serve_expand)
+            .service(serve_follows)
             .service(serve_reload)
             .service(serve_version)
     })
@@ -601,45 +2268,122 @@ fn print_size(label: &str, size: u64) {
     }
 }
 
-pub fn stats(index: &mut dyn Index) -> Result<(), ()> {
-    let (fst_start_offset, fst_end_offset) = read_index_header(index)?;
-    index.seek(SeekFrom::Start(fst_start_offset)).unwrap();
-    let mut fst_reader = index.take(fst_end_offset - fst_start_offset);
-    let mut fst_bytes = vec![];
-    fst_reader.read_to_end(&mut fst_bytes).unwrap();
-    let fst = fst::Map::new(fst_bytes).unwrap();
+pub fn stats(index: &IndexReader) -> Result<(), Error> {
+    let header = &index.header;
+    let fst_section = header.fst_section();
+    let fst = &index.fst;
+    let namespaces = &index.namespaces;
+    let metadata = &index.metadata;
+    let mut cursor = index.cursor();
+    let index = &mut cursor;
     let mut stream = fst.values();
-    let mut user_contribs_offset = fst_start_offset;
-    let mut edit_count_range_counts = vec![0; 10];
+    let mut user_contribs_offset = fst_section.offset;
+    let mut edit_count_range_counts = [0; 10];
     while let Some(value) = stream.next() {
-        let edit_count = value & 0xFF_FF_FF_FF;
+        let edit_count = read_edit_count(index, header, value)?;
         if edit_count > 256 {
             edit_count_range_counts[9] += 1;
         } else {
             edit_count_range_counts[(edit_count as f64).log2().ceil() as usize] += 1;
         }
-        let page_offsets_offset = value >> 32;
+        let page_offsets_offset = page_offsets_offset(header, value);
         if page_offsets_offset < user_contribs_offset {
             user_contribs_offset = page_offsets_offset;
         }
     }
+    if user_contribs_offset < SF_HEADER_LENGTH {
+        return Err(Error::Corrupt(format!("postings start at offset {}, inside the header", user_contribs_offset)));
+    }
+    let mut namespace_page_counts = BTreeMap::new();
+    if header.version > SF_VERSION_WITHOUT_NAMESPACES {
+        for ((namespace, _), page_count) in read_page_counts(index, header, user_contribs_offset)? {
+            *namespace_page_counts.entry(namespace).or_insert(0) += page_count;
+        }
+    }
+    let footer_length = index.seek(SeekFrom::End(0)).map_err(Error::from_index)? - header.footer_offset;
     println!("==[ Header info ]==");
-    println!("Index format version number: {}", SF_VERSION);
+    println!("Index format version number: {}", header.version);
+    if let Some(metadata) = metadata {
+        println!("==[ Metadata ]==");
+        println!("Wiki: {} ({})", metadata.dbname, metadata.base_url);
+        println!("Latest revision: {}", format_timestamp(metadata.dump_time));
+        println!("Built: {} by socksfinder v{}", format_timestamp(metadata.build_time), metadata.socksfinder_version);
+        println!("Pages: {}", metadata.page_count);
+        println!("Users: {}", metadata.user_count);
+        println!("Revisions: {}", metadata.revision_count);
+    }
     println!("==[ Section offsets ]==");
-    println!("Page names: 4");
+    println!("Page names: {}", SF_HEADER_LENGTH);
     println!("User contribs: {}", user_contribs_offset);
-    println!("FST: {}", fst_start_offset);
-    println!("Footer: {}", fst_end_offset);
+    println!("FST: {}", fst_section.offset);
+    if let Some(section) = header.section(SF_SECTION_USERS) {
+        println!("Users: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_PAGE_EDITORS) {
+        println!("Page editors: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_TITLES) {
+        println!("Titles: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_SIGNATURES) {
+        println!("Signatures: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_EDIT_COUNTS) {
+        println!("Edit counts: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_ADJACENCY) {
+        println!("Adjacency: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_NAMESPACES) {
+        println!("Namespaces: {}", section.offset);
+    }
+    if let Some(section) = header.section(SF_SECTION_METADATA) {
+        println!("Metadata: {}", section.offset);
+    }
+    println!("Footer: {}", header.footer_offset);
     println!("==[ Section sizes ]==");
-    print_size("Header", 4);
-    print_size("Page names", user_contribs_offset - 4);
-    print_size("User contribs", fst_start_offset - user_contribs_offset);
-    print_size("FST", fst_end_offset - fst_start_offset);
-    print_size("Footer", 4);
+    print_size("Header", SF_HEADER_LENGTH);
+    print_size("Page names", user_contribs_offset - SF_HEADER_LENGTH);
+    print_size("User contribs", fst_section.offset - user_contribs_offset);
+    print_size("FST", fst_section.length);
+    if let Some(section) = header.section(SF_SECTION_USERS) {
+        print_size("Users", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_PAGE_EDITORS) {
+        print_size("Page editors", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_TITLES) {
+        print_size("Titles", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_SIGNATURES) {
+        print_size("Signatures", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_EDIT_COUNTS) {
+        print_size("Edit counts", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_ADJACENCY) {
+        print_size("Adjacency", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_NAMESPACES) {
+        print_size("Namespaces", section.length);
+    }
+    if let Some(section) = header.section(SF_SECTION_METADATA) {
+        print_size("Metadata", section.length);
+    }
+    print_size("Footer", footer_length);
     println!("==[ Contrib list length ]==");
-    for bin in 0..9 {
-        println!("]{};{}]: {}", if bin == 0 { 0 } else { 1 << (bin - 1) }, 1 << bin, edit_count_range_counts[bin]);
+    for (bin, count) in edit_count_range_counts.iter().enumerate().take(9) {
+        println!("]{};{}]: {}", if bin == 0 { 0 } else { 1 << (bin - 1) }, 1 << bin, count);
     }
     println!("]256;+∞[ : {}", edit_count_range_counts[9]);
+    if !namespace_page_counts.is_empty() {
+        println!("==[ Pages per namespace ]==");
+        for (namespace, page_count) in namespace_page_counts {
+            match namespaces.get(&namespace) {
+                Some(name) if !name.is_empty() => println!("{} ({}): {}", namespace, name, page_count),
+                _ => println!("{}: {}", namespace, page_count),
+            }
+        }
+    }
     Ok(())
 }
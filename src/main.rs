@@ -1,65 +1,198 @@
 use std::{
     fs::File,
     io::{
+        BufRead,
         BufReader,
         BufWriter,
         Write,
     },
+    path::Path,
     process,
 };
 
 use serde_derive::Deserialize;
 
 const USAGE: &str = "
-Usage: socksfinder build <index>
-       socksfinder query [--cooccurrences | --threshold=<threshold>] [--order=<order>] <index> <user>...
+Usage: socksfinder build [--memory=<size>] [--signatures] [--adjacency] <index> [<dump>...]
+       socksfinder query [--cooccurrences [--metric=<metric>] [--max-p-value=<p-value>] [--min-shared-pages=<n>] | --communities [--metric=<metric>] [--max-p-value=<p-value>] | --threshold=<threshold>] [--order=<order>] [--since=<date>] [--until=<date>] [--namespaces=<namespaces>] [--exclude-namespaces=<namespaces>] [--max-page-editors=<n>] [--format=<format>] <index> <user>...
+       socksfinder query --expression=<expression> [--order=<order>] [--since=<date>] [--until=<date>] [--namespaces=<namespaces>] [--exclude-namespaces=<namespaces>] [--max-page-editors=<n>] [--format=<format>] <index>
+       socksfinder page [--threshold=<threshold>] [--order=<order>] [--format=<format>] <index> <title>...
+       socksfinder similar [--estimate] [--top=<n>] [--metric=<metric>] [--exclude-users=<users>] [--since=<date>] [--until=<date>] [--namespaces=<namespaces>] [--exclude-namespaces=<namespaces>] [--max-page-editors=<n>] [--format=<format>] <index> <user>
+       socksfinder expand [--hops=<n>] [--decay=<decay>] [--top=<n>] [--exclude-users=<users>] [--since=<date>] [--until=<date>] [--namespaces=<namespaces>] [--exclude-namespaces=<namespaces>] [--max-page-editors=<n>] [--format=<format>] <index> <user>...
+       socksfinder follows [--order=<order>] [--namespaces=<namespaces>] [--exclude-namespaces=<namespaces>] [--max-page-editors=<n>] [--format=<format>] <index> <user> <followed-user>
        socksfinder serve [--hostname=<hostname>] [--port=<port>] <index>
        socksfinder stats <index>
+       socksfinder update [--memory=<size>] <index> <new-index>
+       socksfinder verify <index>
        socksfinder -h | --help
        socksfinder --version
 
 Commands:
-    build                    Build an index from a MediaWiki XML dump (read on the standard input).
+    build                    Build an index from MediaWiki XML dumps (read on the standard input if none is given).
+    expand                   Search users who have modified the pages of several users in the index, most of them first.
+    follows                  Search pages on which a user has edited right after another user in the index.
+    page                     Search users who have modified one or several pages in the index.
     query                    Search pages modified by several users in the index.
+    similar                  Search users who have modified the same pages as a user in the index, most similar first.
     serve                    Start a small HTTP server to serve the index.
     stats                    Display statistics about the index.
+    update                   Build a new index from the index and a MediaWiki XML dump of newer revisions (read on the standard input, optionally compressed).
+    verify                   Check that the index is neither corrupt nor truncated.
 
 Arguments:
+    dump                     MediaWiki XML dump, optionally compressed using gzip, bzip2 or zstd.
+    followed-user            User right after whom the user has edited.
     index                    Index built from a MediaWiki dump.
-    user                     User which has modified pages to look for.
+    new-index                Index to build from the index and the newer revisions.
+    title                    Title of a page, including its namespace, whose editors to look for.
+    user                     User which has modified pages to look for (a seed for expand).
 
 Options:
+    --adjacency              Record which users edit right after which others on each page, to search them with follows (28 bytes per pair of users following each other on a page).
+    --communities            Show the groups of users who have modified more pages together than with the others instead of the page names.
     --cooccurrences          Show the co-occurrences matrix instead of the page names.
+    --decay=<decay>          Weight of the users found at each hop as seeds of the next one, relative to their own seeds [default: 0.5].
+    --estimate               Only check the users whose signature is close to the one of the user, which is much faster.
+    --exclude-namespaces=<namespaces>
+                             Don't consider pages in these namespaces (comma-separated ids or names).
+    --exclude-users=<users>  Don't show these users (comma-separated), eg. bots.
+    --expression=<expression>
+                             Search pages matching a combination of users with & (and), | (or), ! (not), <n> of (...) (at least n of the comma-separated expressions) and parentheses.
+    --format=<format>        Format of results [default: text].
+                             Valid formats: text, json, jsonl, csv, tsv, wikitext, and for the co-occurrences matrix dot, graphml, gexf.
     -h, --help               Show this screen.
+    --hops=<n>               Number of times the top users found are added to the seeds to search more users [default: 1].
     --hostname=<hostname>    Hostname to resolve to find the network interface to serve the index [default: localhost].
+    --max-page-editors=<n>   Don't consider pages modified by more than n distinct users in total.
+    --max-p-value=<p-value>  Only show users which have pages in common with another user with at most this p-value.
+    --memory=<size>          Approximate memory used to build or update the index before resorting to temporary files (eg. 512M, 1G).
+    --metric=<metric>        Similarity of users in the co-occurrences matrix, its communities or with the user, also used to order them [default: count].
+                             Valid metrics: count, jaccard, overlap, cosine, dice, significance.
+    --min-shared-pages=<n>   Only link users with at least n pages in common in graph formats [default: 1].
+    --namespaces=<namespaces>
+                             Only consider pages in these namespaces (comma-separated ids or names).
     --order=<order>          Order of results, none can be faster and consume less memory [default: none].
-                             Valid orders: none, count_decreasing, count_increasing, alphabetical.
+                             Valid orders: none, count_decreasing, count_increasing, alphabetical, rarity.
     --port=<port>            Port on which to serve the index [default: 8080].
-    --threshold=<threshold>  Number of different editors, 0 for all of them [default: 0].
+    --signatures             Compute signatures of the pages of each user, to search similar users with --estimate (256 bytes of memory per user).
+    --since=<date>           Only consider edits made on or after this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ).
+    --threshold=<threshold>  Number of different editors (or pages for page), 0 for all of them (default for query, 1 for page).
+    --top=<n>                Number of users to show (at each hop for expand), 0 for all of them [default: 10].
+    --until=<date>           Only consider edits made on or before this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ).
     --version                Show version.
 ";
 
 #[derive(Deserialize)]
 struct Args {
     cmd_build: bool,
+    cmd_expand: bool,
+    cmd_follows: bool,
+    cmd_page: bool,
     cmd_query: bool,
     cmd_serve: bool,
+    cmd_similar: bool,
     cmd_stats: bool,
+    cmd_update: bool,
+    cmd_verify: bool,
+    arg_dump: Vec<String>,
+    arg_followed_user: String,
     arg_index: String,
+    arg_new_index: String,
+    arg_title: Vec<String>,
     arg_user: Vec<String>,
+    flag_adjacency: bool,
+    flag_communities: bool,
     flag_cooccurrences: bool,
+    flag_decay: f64,
+    flag_estimate: bool,
+    flag_exclude_namespaces: Option<String>,
+    flag_exclude_users: Option<String>,
+    flag_expression: Option<String>,
+    flag_format: socksfinder::Format,
+    flag_hops: usize,
     flag_hostname: String,
+    flag_max_page_editors: Option<u32>,
+    flag_max_p_value: Option<f64>,
+    flag_memory: Option<String>,
+    flag_metric: socksfinder::Metric,
+    flag_min_shared_pages: u64,
+    flag_namespaces: Option<String>,
     flag_order: socksfinder::Order,
     flag_port: u16,
-    flag_threshold: usize,
+    flag_signatures: bool,
+    flag_since: Option<String>,
+    flag_threshold: Option<usize>,
+    flag_top: usize,
+    flag_until: Option<String>,
     flag_version: bool,
 }
 
+fn parse_date(date: &str, end_of_day: bool) -> u32 {
+    socksfinder::parse_date(date, end_of_day).unwrap_or_else(|| {
+        eprintln!("socksfinder: invalid date: {} (expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ)", date);
+        process::exit(1);
+    })
+}
+
+fn parse_size(size: &str) -> u64 {
+    let digits = size.trim_end_matches(|character: char| !character.is_ascii_digit());
+    let multiplier = match size[digits.len()..].trim_end_matches("iB").trim_end_matches('B') {
+        "" => Some(1),
+        "K" | "k" => Some(1 << 10),
+        "M" => Some(1 << 20),
+        "G" => Some(1 << 30),
+        "T" => Some(1 << 40),
+        _ => None,
+    };
+    match (digits.parse::<u64>(), multiplier) {
+        (Ok(number), Some(multiplier)) => number * multiplier,
+        _ => {
+            eprintln!("socksfinder: invalid size: {} (expected a number of bytes, optionally followed by K, M, G or T)", size);
+            process::exit(1);
+        },
+    }
+}
+
+fn open_dump<'a>(reader: Box<dyn BufRead + 'a>, name: &str) -> Box<dyn BufRead + 'a> {
+    socksfinder::open_dump(reader).unwrap_or_else(|cause| {
+        eprintln!("socksfinder: can't read dump: {}: {}", name, &cause);
+        process::exit(1);
+    })
+}
+
+// Report an error, about a file if given, and exit with the matching code
+fn fail(error: socksfinder::Error, path: Option<&str>) -> ! {
+    match path {
+        Some(path) => eprintln!("socksfinder: {}: {}", path, error),
+        None => eprintln!("socksfinder: {}", error),
+    }
+    process::exit(error.exit_code());
+}
+
+fn split_list(list: Option<String>) -> Vec<String> {
+    match list {
+        None => vec![],
+        Some(list) => list.split(',').map(|item| item.to_string()).collect(),
+    }
+}
+
+// Restrictions on the edits given by the filter options, which are absent
+// (and thus don't restrict anything) for commands which don't take them
+fn filter(args: &Args) -> socksfinder::Filter {
+    socksfinder::Filter {
+        since: args.flag_since.as_ref().map(|since| parse_date(since, false)),
+        until: args.flag_until.as_ref().map(|until| parse_date(until, true)),
+        namespaces: split_list(args.flag_namespaces.clone()),
+        excluded_namespaces: split_list(args.flag_exclude_namespaces.clone()),
+        max_page_editors: args.flag_max_page_editors,
+    }
+}
+
 fn main() {
     let args: Args =
         docopt::Docopt::new(USAGE)
             .and_then(|docopts|
-                docopts.argv(std::env::args().into_iter())
+                docopts.argv(std::env::args())
                    .deserialize()
             )
             .unwrap_or_else(|error|
@@ -74,35 +207,159 @@ fn main() {
                 eprintln!("socksfinder: can't open index: {}: {}", &args.arg_index, &cause);
                 process::exit(1);
             });
+            let options = socksfinder::BuildOptions {
+                memory_budget: args.flag_memory.map(|memory| parse_size(&memory)),
+                // for reproducible builds, see https://reproducible-builds.org/specs/source-date-epoch/
+                build_time: std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.parse().ok()),
+                signatures: args.flag_signatures,
+                adjacency: args.flag_adjacency,
+            };
+            let mut dumps = vec![];
+            if args.arg_dump.is_empty() {
+                dumps.push(open_dump(Box::new(std::io::stdin().lock()), "standard input"));
+            } else {
+                for dump in &args.arg_dump {
+                    let input = File::open(dump).unwrap_or_else(|cause| {
+                        eprintln!("socksfinder: can't open dump: {}: {}", dump, &cause);
+                        process::exit(1);
+                    });
+                    dumps.push(open_dump(Box::new(BufReader::new(input)), dump));
+                }
+            }
+            let mut readers: Vec<&mut dyn BufRead> = dumps.iter_mut()
+                .map(|dump| dump.as_mut() as &mut dyn BufRead)
+                .collect();
             let mut buffered_output = BufWriter::new(output);
-            if socksfinder::build(&mut std::io::stdin().lock(), &mut buffered_output).is_err() ||
-               buffered_output.flush().is_err() {
-                process::exit(1);
+            if let Err(error) = socksfinder::build(&mut readers, &mut buffered_output, &options) {
+                fail(error, None);
+            }
+            if let Err(cause) = buffered_output.flush() {
+                fail(socksfinder::Error::Io(cause), Some(&args.arg_index));
             }
         } else if args.cmd_query {
-            let input = File::open(&args.arg_index).unwrap_or_else(|cause| {
-                eprintln!("socksfinder: can't open index: {}: {}", &args.arg_index, &cause);
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
+            });
+            let filter = filter(&args);
+            let expression = args.flag_expression.map(|expression| expression.parse::<socksfinder::Expression>().unwrap_or_else(|error| {
+                fail(error, None);
+            }));
+            let mut output = std::io::stdout();
+            let result = if args.flag_communities {
+                socksfinder::communities(&index, &mut output, &args.arg_user, &filter, args.flag_metric, args.flag_max_p_value, args.flag_format)
+            } else {
+                socksfinder::query(&index, &mut output, &args.arg_user, args.flag_threshold.unwrap_or(0), expression.as_ref(), args.flag_order, &filter, args.flag_cooccurrences, args.flag_metric, args.flag_max_p_value, args.flag_min_shared_pages, args.flag_format, true)
+            };
+            if output.flush().is_err() {
                 process::exit(1);
+            }
+            if let Err(error) = result {
+                fail(error, None);
+            }
+        } else if args.cmd_page {
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
             });
-            let mut buffered_input = BufReader::new(input);
             let mut output = std::io::stdout();
-            if socksfinder::query(&mut buffered_input, &mut output, &args.arg_user, args.flag_threshold, args.flag_order, args.flag_cooccurrences, true).is_err() ||
-               output.flush().is_err() {
+            let result = socksfinder::page(&index, &mut output, &args.arg_title, args.flag_threshold.unwrap_or(1), args.flag_order, args.flag_format);
+            if output.flush().is_err() {
+                process::exit(1);
+            }
+            if let Err(error) = result {
+                fail(error, None);
+            }
+        } else if args.cmd_expand {
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
+            });
+            let filter = filter(&args);
+            let mut output = std::io::stdout();
+            let result = socksfinder::expand(&index, &mut output, &args.arg_user, &filter, &split_list(args.flag_exclude_users), args.flag_hops, args.flag_decay, args.flag_top, args.flag_format);
+            if output.flush().is_err() {
+                process::exit(1);
+            }
+            if let Err(error) = result {
+                fail(error, None);
+            }
+        } else if args.cmd_similar {
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
+            });
+            let filter = filter(&args);
+            let mut output = std::io::stdout();
+            let result = socksfinder::similar(&index, &mut output, &args.arg_user[0], &filter, &split_list(args.flag_exclude_users), args.flag_metric, args.flag_top, args.flag_estimate, args.flag_format);
+            if output.flush().is_err() {
                 process::exit(1);
             }
+            if let Err(error) = result {
+                fail(error, None);
+            }
+        } else if args.cmd_follows {
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
+            });
+            let filter = filter(&args);
+            let mut output = std::io::stdout();
+            let result = socksfinder::follows(&index, &mut output, &args.arg_user[0], &args.arg_followed_user, args.flag_order, &filter, args.flag_format);
+            if output.flush().is_err() {
+                process::exit(1);
+            }
+            if let Err(error) = result {
+                fail(error, None);
+            }
         } else if args.cmd_serve {
             if socksfinder::serve(args.arg_index, args.flag_hostname, args.flag_port).is_err() {
                 process::exit(1);
             }
         } else if args.cmd_stats {
+            let index = socksfinder::IndexReader::open(Path::new(&args.arg_index)).unwrap_or_else(|error| {
+                fail(error, Some(&args.arg_index));
+            });
+            if let Err(error) = socksfinder::stats(&index) {
+                fail(error, Some(&args.arg_index));
+            }
+        } else if args.cmd_update {
+            let input = File::open(&args.arg_index).unwrap_or_else(|cause| {
+                eprintln!("socksfinder: can't open index: {}: {}", &args.arg_index, &cause);
+                process::exit(1);
+            });
+            match (std::fs::canonicalize(&args.arg_index), std::fs::canonicalize(&args.arg_new_index)) {
+                (Ok(index_path), Ok(new_index_path)) if index_path == new_index_path => {
+                    eprintln!("socksfinder: can't update index in place: {}", &args.arg_index);
+                    process::exit(1);
+                },
+                _ => (),
+            }
+            let output = File::create(&args.arg_new_index).unwrap_or_else(|cause| {
+                eprintln!("socksfinder: can't open index: {}: {}", &args.arg_new_index, &cause);
+                process::exit(1);
+            });
+            let mut buffered_input = BufReader::new(input);
+            let mut buffered_output = BufWriter::new(output);
+            let mut dump = open_dump(Box::new(std::io::stdin().lock()), "standard input");
+            let summary = socksfinder::update(&mut buffered_input, &mut dump, &mut buffered_output, args.flag_memory.map(|memory| parse_size(&memory))).unwrap_or_else(|error| {
+                fail(error, None);
+            });
+            if summary.dropped_adjacency {
+                eprintln!("socksfinder: warning: the revision adjacency isn't kept by updates (rebuild the index with --adjacency to keep it)");
+            }
+            if let Err(cause) = buffered_output.flush() {
+                fail(socksfinder::Error::Io(cause), Some(&args.arg_new_index));
+            }
+        } else if args.cmd_verify {
             let input = File::open(&args.arg_index).unwrap_or_else(|cause| {
                 eprintln!("socksfinder: can't open index: {}: {}", &args.arg_index, &cause);
                 process::exit(1);
             });
             let mut buffered_input = BufReader::new(input);
-            if socksfinder::stats(&mut buffered_input).is_err() {
+            let mut output = std::io::stdout();
+            let result = socksfinder::verify(&mut buffered_input, &mut output);
+            if output.flush().is_err() {
                 process::exit(1);
             }
+            if let Err(error) = result {
+                fail(error, Some(&args.arg_index));
+            }
         }
     }
 }
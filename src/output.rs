@@ -0,0 +1,753 @@
+use quick_xml::escape::escape;
+
+use prettytable::{
+    Cell,
+    Row,
+    Table,
+};
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+};
+
+use crate::{
+    Communities,
+    CooccurrenceMatrix,
+    EditorMatch,
+    ExpandedUser,
+    FollowedPage,
+    Format,
+    Metric,
+    PageMatch,
+    SimilarUser,
+};
+
+const SF_SPECIAL_NAMESPACE: i32 = -1;
+
+// Prefix of the links to the contributions of users, in the language of the
+// wiki if the index knows the name of its special namespace
+fn contributions_prefix(namespaces: &BTreeMap<i32, String>) -> String {
+    match namespaces.get(&SF_SPECIAL_NAMESPACE) {
+        Some(name) if !name.is_empty() => format!("{}:Contributions/", name),
+        _ => "Special:Contributions/".to_string(),
+    }
+}
+
+fn user_link(contributions_prefix: &str, user: &str) -> String {
+    format!("[[{}{}|{}]]", contributions_prefix, user, user)
+}
+
+// Cell of the co-occurrences matrix, empty on the diagonal; similarities other
+// than counts are ratios, rounded for readability, except for the significance
+// which is shown as a p-value
+fn matrix_cell(matrix: &CooccurrenceMatrix, metric: Metric, row: usize, column: usize) -> String {
+    if row == column {
+        String::new()
+    } else if metric == Metric::count {
+        matrix.count(row, column).to_string()
+    } else if metric == Metric::significance {
+        format!("{:.2e}", matrix.p_value(row, column))
+    } else {
+        format!("{:.3}", matrix.similarity(row, column, metric))
+    }
+}
+
+// Similarity given along with the count in JSON, the p-value for the
+// significance
+fn json_similarity(matrix: &CooccurrenceMatrix, metric: Metric, row: usize, column: usize) -> f64 {
+    if metric == Metric::significance {
+        matrix.p_value(row, column)
+    } else {
+        matrix.similarity(row, column, metric)
+    }
+}
+
+// Similarity of a user given along with the count, the p-value for the
+// significance
+fn user_similarity(user: &SimilarUser, metric: Metric) -> f64 {
+    if metric == Metric::significance {
+        10f64.powf(-user.similarity)
+    } else {
+        user.similarity
+    }
+}
+
+// Similarity of a user rounded for readability, like in the co-occurrences
+// matrix
+fn user_similarity_cell(user: &SimilarUser, metric: Metric) -> String {
+    if metric == Metric::significance {
+        format!("{:.2e}", user_similarity(user, metric))
+    } else {
+        format!("{:.3}", user_similarity(user, metric))
+    }
+}
+
+// Total number of editors of a page, or nothing if the index doesn't know it
+fn total_editor_count(page: &PageMatch) -> String {
+    page.total_editor_count.map_or_else(String::new, |total_editor_count| total_editor_count.to_string())
+}
+
+enum PageSink<'a> {
+    Raw(&'a mut dyn Write),
+    Delimited(Box<csv::Writer<&'a mut dyn Write>>),
+}
+
+// Write pages one at a time in any format, so that they don't have to be kept
+// in memory
+pub(crate) struct PageWriter<'a> {
+    sink: PageSink<'a>,
+    format: Format,
+    page_count: usize,
+    contributions_prefix: String,
+}
+
+fn delimited_writer(writer: &mut dyn Write, format: Format) -> csv::Writer<&mut dyn Write> {
+    csv::WriterBuilder::new()
+        .delimiter(if format == Format::tsv { b'\t' } else { b',' })
+        .from_writer(writer)
+}
+
+impl<'a> PageWriter<'a> {
+    pub(crate) fn new(writer: &'a mut dyn Write, format: Format, namespaces: &BTreeMap<i32, String>) -> std::io::Result<Self> {
+        let sink = match format {
+            Format::csv | Format::tsv => {
+                let mut writer = delimited_writer(writer, format);
+                writer.write_record(["title", "namespace", "editor_count", "total_editor_count", "editors"])?;
+                PageSink::Delimited(Box::new(writer))
+            },
+            Format::wikitext => {
+                writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+                writeln!(writer, "! Page !! Editor count !! Total editor count !! Editors")?;
+                PageSink::Raw(writer)
+            },
+            _ => PageSink::Raw(writer),
+        };
+        Ok(PageWriter {
+            sink,
+            format,
+            page_count: 0,
+            contributions_prefix: contributions_prefix(namespaces),
+        })
+    }
+
+    pub(crate) fn write(&mut self, page: &PageMatch) -> std::io::Result<()> {
+        match &mut self.sink {
+            PageSink::Raw(writer) => match self.format {
+                Format::json => {
+                    writer.write_all(if self.page_count == 0 { b"[" } else { b"," })?;
+                    serde_json::to_writer(&mut *writer, page)?;
+                },
+                Format::jsonl => {
+                    serde_json::to_writer(&mut *writer, page)?;
+                    writeln!(writer)?;
+                },
+                // the leading colon links to categories and files instead of including them
+                Format::wikitext => {
+                    let editors: Vec<_> = page.editors.iter()
+                        .map(|editor| user_link(&self.contributions_prefix, editor))
+                        .collect();
+                    writeln!(writer, "|-")?;
+                    writeln!(writer, "| [[:{}]] || {} || {} || {}", page.title, page.editor_count, total_editor_count(page), editors.join(", "))?;
+                },
+                _ => match page.total_editor_count {
+                    Some(total_editor_count) => writeln!(writer, "{}: {}/{} ({})", page.title, page.editor_count, total_editor_count, page.editors.join(", "))?,
+                    None => writeln!(writer, "{}: {} ({})", page.title, page.editor_count, page.editors.join(", "))?,
+                },
+            },
+            // editors are separated by |, which can't be part of a user name
+            PageSink::Delimited(writer) => writer.write_record([
+                page.title.as_str(),
+                &page.namespace.to_string(),
+                &page.editor_count.to_string(),
+                &total_editor_count(page),
+                &page.editors.join("|"),
+            ])?,
+        }
+        self.page_count += 1;
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> std::io::Result<()> {
+        match self.sink {
+            PageSink::Raw(writer) => {
+                match self.format {
+                    Format::json => writeln!(writer, "{}]", if self.page_count == 0 { "[" } else { "" })?,
+                    Format::wikitext => writeln!(writer, "|}}")?,
+                    _ => (),
+                }
+                Ok(())
+            },
+            PageSink::Delimited(mut writer) => writer.flush(),
+        }
+    }
+}
+
+// Write a co-occurrences matrix in any format; the text format is a table,
+// which is written with colors on the standard output if try_format is true
+pub(crate) fn write_matrix(writer: &mut dyn Write, format: Format, matrix: &CooccurrenceMatrix, metric: Metric, min_shared_pages: u64, namespaces: &BTreeMap<i32, String>, try_format: bool) -> std::io::Result<()> {
+    let users = matrix.users();
+    match format {
+        Format::text => {
+            let mut table = Table::new();
+            let mut row = vec![Cell::new("")];
+            for user in users {
+                row.push(Cell::new(user).style_spec("b"));
+            }
+            table.add_row(Row::new(row));
+            for (row_position, row_user) in users.iter().enumerate() {
+                let mut row = vec![Cell::new(row_user).style_spec("b")];
+                for cell_position in 0..users.len() {
+                    row.push(Cell::new(&matrix_cell(matrix, metric, row_position, cell_position)));
+                }
+                table.add_row(Row::new(row));
+            }
+            if try_format {
+                table.printstd();
+            } else {
+                table.print(writer)?;
+            }
+        },
+        // similarities other than counts are given along with the counts
+        Format::json => {
+            let counts: Vec<Vec<u64>> = (0..users.len())
+                .map(|row| (0..users.len()).map(|column| matrix.count(row, column)).collect())
+                .collect();
+            let mut object = serde_json::json!({
+                "users": users,
+                "counts": counts,
+            });
+            if metric != Metric::count {
+                let similarities: Vec<Vec<f64>> = (0..users.len())
+                    .map(|row| (0..users.len()).map(|column| json_similarity(matrix, metric, row, column)).collect())
+                    .collect();
+                object[if metric == Metric::significance { "p_values" } else { metric.name() }] = serde_json::json!(similarities);
+            }
+            serde_json::to_writer(&mut *writer, &object)?;
+            writeln!(writer)?;
+        },
+        // one line per pair of users
+        Format::jsonl => {
+            for (first_position, first_user) in users.iter().enumerate() {
+                for (second_position, second_user) in users.iter().enumerate().skip(first_position + 1) {
+                    let mut object = serde_json::json!({
+                        "first_user": first_user,
+                        "second_user": second_user,
+                        "count": matrix.count(first_position, second_position),
+                    });
+                    if metric != Metric::count {
+                        object[if metric == Metric::significance { "p_value" } else { metric.name() }] = serde_json::json!(json_similarity(matrix, metric, first_position, second_position));
+                    }
+                    serde_json::to_writer(&mut *writer, &object)?;
+                    writeln!(writer)?;
+                }
+            }
+        },
+        Format::wikitext => {
+            let contributions_prefix = contributions_prefix(namespaces);
+            let links: Vec<_> = users.iter()
+                .map(|user| user_link(&contributions_prefix, user))
+                .collect();
+            writeln!(writer, "{{| class=\"wikitable\"")?;
+            writeln!(writer, "! !! {}", links.join(" !! "))?;
+            for (row_position, row_link) in links.iter().enumerate() {
+                let cells: Vec<_> = (0..users.len())
+                    .map(|cell_position| matrix_cell(matrix, metric, row_position, cell_position))
+                    .collect();
+                writeln!(writer, "|-")?;
+                writeln!(writer, "! {}", row_link)?;
+                writeln!(writer, "| {}", cells.join(" || "))?;
+            }
+            writeln!(writer, "|}}")?;
+        },
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            let mut record = vec!["user".to_string()];
+            record.extend(users.iter().cloned());
+            writer.write_record(&record)?;
+            for (row_position, row_user) in users.iter().enumerate() {
+                record.clear();
+                record.push(row_user.clone());
+                for cell_position in 0..users.len() {
+                    record.push(matrix_cell(matrix, metric, row_position, cell_position));
+                }
+                writer.write_record(&record)?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => write_graph(writer, format, matrix, metric, min_shared_pages)?,
+    }
+    Ok(())
+}
+
+// Name of the similarity given along with the count in JSON and graphs, if any
+fn similarity_name(metric: Metric) -> Option<&'static str> {
+    match metric {
+        Metric::count => None,
+        Metric::significance => Some("p_value"),
+        _ => Some(metric.name()),
+    }
+}
+
+fn dot_string(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Write a co-occurrences matrix as a graph, whose nodes are the users, with
+// their edit count (unless the index has none) and page count, and whose
+// edges link the users with at least min_shared_pages pages in common,
+// weighted by this number, along with their similarity
+fn write_graph(writer: &mut dyn Write, format: Format, matrix: &CooccurrenceMatrix, metric: Metric, min_shared_pages: u64) -> std::io::Result<()> {
+    let users = matrix.users();
+    let edges: Vec<(usize, usize)> = (0..users.len())
+        .flat_map(|first_user| (first_user + 1..users.len()).map(move |second_user| (first_user, second_user)))
+        .filter(|(first_user, second_user)| matrix.count(*first_user, *second_user) >= min_shared_pages.max(1))
+        .collect();
+    let similarity_name = similarity_name(metric);
+    match format {
+        Format::dot => {
+            writeln!(writer, "graph cooccurrences {{")?;
+            for (position, user) in users.iter().enumerate() {
+                match matrix.edit_count(position) {
+                    Some(edit_count) => writeln!(writer, "  {} [edit_count={}, page_count={}];", dot_string(user), edit_count, matrix.page_count(position))?,
+                    None => writeln!(writer, "  {} [page_count={}];", dot_string(user), matrix.page_count(position))?,
+                }
+            }
+            for (first_user, second_user) in edges {
+                let count = matrix.count(first_user, second_user);
+                write!(writer, "  {} -- {} [weight={}, label={}", dot_string(&users[first_user]), dot_string(&users[second_user]), count, count)?;
+                if let Some(similarity_name) = similarity_name {
+                    write!(writer, ", {}={}", similarity_name, json_similarity(matrix, metric, first_user, second_user))?;
+                }
+                writeln!(writer, "];")?;
+            }
+            writeln!(writer, "}}")?;
+        },
+        Format::graphml => {
+            writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+            writeln!(writer, "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>")?;
+            writeln!(writer, "  <key id=\"edit_count\" for=\"node\" attr.name=\"edit_count\" attr.type=\"long\"/>")?;
+            writeln!(writer, "  <key id=\"page_count\" for=\"node\" attr.name=\"page_count\" attr.type=\"long\"/>")?;
+            writeln!(writer, "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"long\"/>")?;
+            if let Some(similarity_name) = similarity_name {
+                writeln!(writer, "  <key id=\"{}\" for=\"edge\" attr.name=\"{}\" attr.type=\"double\"/>", similarity_name, similarity_name)?;
+            }
+            writeln!(writer, "  <graph id=\"cooccurrences\" edgedefault=\"undirected\">")?;
+            for (position, user) in users.iter().enumerate() {
+                write!(writer, "    <node id=\"n{}\"><data key=\"label\">{}</data>", position, escape(user))?;
+                if let Some(edit_count) = matrix.edit_count(position) {
+                    write!(writer, "<data key=\"edit_count\">{}</data>", edit_count)?;
+                }
+                writeln!(writer, "<data key=\"page_count\">{}</data></node>", matrix.page_count(position))?;
+            }
+            for (first_user, second_user) in edges {
+                write!(writer, "    <edge source=\"n{}\" target=\"n{}\"><data key=\"weight\">{}</data>", first_user, second_user, matrix.count(first_user, second_user))?;
+                if let Some(similarity_name) = similarity_name {
+                    write!(writer, "<data key=\"{}\">{}</data>", similarity_name, json_similarity(matrix, metric, first_user, second_user))?;
+                }
+                writeln!(writer, "</edge>")?;
+            }
+            writeln!(writer, "  </graph>")?;
+            writeln!(writer, "</graphml>")?;
+        },
+        // GEXF
+        _ => {
+            writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(writer, "<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">")?;
+            writeln!(writer, "  <graph defaultedgetype=\"undirected\">")?;
+            writeln!(writer, "    <attributes class=\"node\">")?;
+            writeln!(writer, "      <attribute id=\"edit_count\" title=\"edit_count\" type=\"long\"/>")?;
+            writeln!(writer, "      <attribute id=\"page_count\" title=\"page_count\" type=\"long\"/>")?;
+            writeln!(writer, "    </attributes>")?;
+            if let Some(similarity_name) = similarity_name {
+                writeln!(writer, "    <attributes class=\"edge\">")?;
+                writeln!(writer, "      <attribute id=\"{}\" title=\"{}\" type=\"double\"/>", similarity_name, similarity_name)?;
+                writeln!(writer, "    </attributes>")?;
+            }
+            writeln!(writer, "    <nodes>")?;
+            for (position, user) in users.iter().enumerate() {
+                write!(writer, "      <node id=\"{}\" label=\"{}\"><attvalues>", position, escape(user))?;
+                if let Some(edit_count) = matrix.edit_count(position) {
+                    write!(writer, "<attvalue for=\"edit_count\" value=\"{}\"/>", edit_count)?;
+                }
+                writeln!(writer, "<attvalue for=\"page_count\" value=\"{}\"/></attvalues></node>", matrix.page_count(position))?;
+            }
+            writeln!(writer, "    </nodes>")?;
+            writeln!(writer, "    <edges>")?;
+            for (edge, (first_user, second_user)) in edges.into_iter().enumerate() {
+                write!(writer, "      <edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"", edge, first_user, second_user, matrix.count(first_user, second_user))?;
+                match similarity_name {
+                    Some(similarity_name) => writeln!(writer, "><attvalues><attvalue for=\"{}\" value=\"{}\"/></attvalues></edge>", similarity_name, json_similarity(matrix, metric, first_user, second_user))?,
+                    None => writeln!(writer, "/>")?,
+                }
+            }
+            writeln!(writer, "    </edges>")?;
+            writeln!(writer, "  </graph>")?;
+            writeln!(writer, "</gexf>")?;
+        },
+    }
+    Ok(())
+}
+
+// Graph formats are refused before anything is written for results which
+// aren't a co-occurrences matrix
+fn graph_format_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "graph formats can only be used for the co-occurrences matrix")
+}
+
+// Write the users who have modified several pages in any format
+pub(crate) fn write_editors(writer: &mut dyn Write, format: Format, editors: &[EditorMatch], namespaces: &BTreeMap<i32, String>) -> std::io::Result<()> {
+    match format {
+        Format::text => {
+            for editor in editors {
+                writeln!(writer, "{}: {} ({})", editor.user, editor.page_count, editor.pages.join(", "))?;
+            }
+        },
+        Format::json => {
+            serde_json::to_writer(&mut *writer, editors)?;
+            writeln!(writer)?;
+        },
+        Format::jsonl => {
+            for editor in editors {
+                serde_json::to_writer(&mut *writer, editor)?;
+                writeln!(writer)?;
+            }
+        },
+        // the leading colon links to categories and files instead of including them
+        Format::wikitext => {
+            let contributions_prefix = contributions_prefix(namespaces);
+            writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+            writeln!(writer, "! Editor !! Page count !! Pages")?;
+            for editor in editors {
+                let pages: Vec<_> = editor.pages.iter()
+                    .map(|page| format!("[[:{}]]", page))
+                    .collect();
+                writeln!(writer, "|-")?;
+                writeln!(writer, "| {} || {} || {}", user_link(&contributions_prefix, &editor.user), editor.page_count, pages.join(", "))?;
+            }
+            writeln!(writer, "|}}")?;
+        },
+        // pages are separated by |, which can't be part of a title
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            writer.write_record(["user", "page_count", "pages"])?;
+            for editor in editors {
+                writer.write_record([
+                    editor.user.as_str(),
+                    &editor.page_count.to_string(),
+                    &editor.pages.join("|"),
+                ])?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => return Err(graph_format_error()),
+    }
+    Ok(())
+}
+
+// Write the users most similar to another one in any format; similarities
+// other than counts are given along with the counts, and so are the estimated
+// Jaccard indexes of the users found by signature
+pub(crate) fn write_similar_users(writer: &mut dyn Write, format: Format, users: &[SimilarUser], metric: Metric, namespaces: &BTreeMap<i32, String>) -> std::io::Result<()> {
+    let similarity_name = if metric == Metric::significance { "p_value" } else { metric.name() };
+    let estimated = users.iter().any(|user| user.estimated_jaccard.is_some());
+    // similarity and estimate, as given after the counts
+    let cells = |user: &SimilarUser, rounded: bool| {
+        let mut cells = vec![];
+        if metric != Metric::count {
+            cells.push(if rounded { user_similarity_cell(user, metric) } else { user_similarity(user, metric).to_string() });
+        }
+        if estimated {
+            cells.push(user.estimated_jaccard.map_or_else(String::new, |estimated_jaccard| if rounded { format!("{:.3}", estimated_jaccard) } else { estimated_jaccard.to_string() }));
+        }
+        cells
+    };
+    let mut header = vec![];
+    if metric != Metric::count {
+        header.push(similarity_name);
+    }
+    if estimated {
+        header.push("estimated_jaccard");
+    }
+    match format {
+        Format::text => {
+            for user in users {
+                let mut cells = cells(user, true);
+                if estimated {
+                    let estimate = cells.pop().unwrap_or_default();
+                    cells.push(format!("estimated {}", estimate));
+                }
+                if cells.is_empty() {
+                    writeln!(writer, "{}: {}/{}", user.user, user.count, user.page_count)?;
+                } else {
+                    writeln!(writer, "{}: {}/{} ({})", user.user, user.count, user.page_count, cells.join(", "))?;
+                }
+            }
+        },
+        Format::json | Format::jsonl => {
+            let objects: Vec<_> = users.iter()
+                .map(|user| {
+                    let mut object = serde_json::json!({
+                        "user": user.user,
+                        "count": user.count,
+                        "page_count": user.page_count,
+                    });
+                    if metric != Metric::count {
+                        object[similarity_name] = serde_json::json!(user_similarity(user, metric));
+                    }
+                    if estimated {
+                        object["estimated_jaccard"] = serde_json::json!(user.estimated_jaccard);
+                    }
+                    object
+                })
+                .collect();
+            if format == Format::json {
+                serde_json::to_writer(&mut *writer, &objects)?;
+                writeln!(writer)?;
+            } else {
+                for object in objects {
+                    serde_json::to_writer(&mut *writer, &object)?;
+                    writeln!(writer)?;
+                }
+            }
+        },
+        Format::wikitext => {
+            let contributions_prefix = contributions_prefix(namespaces);
+            writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+            let mut header_cells = vec!["Editor", "Pages in common", "Page count"];
+            header_cells.extend(&header);
+            writeln!(writer, "! {}", header_cells.join(" !! "))?;
+            for user in users {
+                let mut row = vec![user_link(&contributions_prefix, &user.user), user.count.to_string(), user.page_count.to_string()];
+                row.extend(cells(user, true));
+                writeln!(writer, "|-")?;
+                writeln!(writer, "| {}", row.join(" || "))?;
+            }
+            writeln!(writer, "|}}")?;
+        },
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            let mut header_cells = vec!["user", "count", "page_count"];
+            header_cells.extend(&header);
+            writer.write_record(&header_cells)?;
+            for user in users {
+                let mut record = vec![user.user.clone(), user.count.to_string(), user.page_count.to_string()];
+                record.extend(cells(user, false));
+                writer.write_record(&record)?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => return Err(graph_format_error()),
+    }
+    Ok(())
+}
+
+// Write the users found by expanding seeds in any format; in text, scores are
+// only written after the first hop, where they are the same as the counts
+pub(crate) fn write_expanded_users(writer: &mut dyn Write, format: Format, users: &[ExpandedUser], namespaces: &BTreeMap<i32, String>) -> std::io::Result<()> {
+    match format {
+        Format::text => {
+            for user in users {
+                if user.hop == 1 {
+                    writeln!(writer, "{}: {} seeds, {} pages", user.user, user.seed_count, user.page_count)?;
+                } else {
+                    writeln!(writer, "{}: {} seeds, {} pages (hop {}, scores {:.3}, {:.3})", user.user, user.seed_count, user.page_count, user.hop, user.seed_score, user.page_score)?;
+                }
+            }
+        },
+        Format::json => {
+            serde_json::to_writer(&mut *writer, users)?;
+            writeln!(writer)?;
+        },
+        Format::jsonl => {
+            for user in users {
+                serde_json::to_writer(&mut *writer, user)?;
+                writeln!(writer)?;
+            }
+        },
+        Format::wikitext => {
+            let contributions_prefix = contributions_prefix(namespaces);
+            writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+            writeln!(writer, "! Editor !! Hop !! Seeds !! Pages !! Seed score !! Page score")?;
+            for user in users {
+                writeln!(writer, "|-")?;
+                writeln!(writer, "| {} || {} || {} || {} || {:.3} || {:.3}", user_link(&contributions_prefix, &user.user), user.hop, user.seed_count, user.page_count, user.seed_score, user.page_score)?;
+            }
+            writeln!(writer, "|}}")?;
+        },
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            writer.write_record(["user", "hop", "seed_count", "page_count", "seed_score", "page_score"])?;
+            for user in users {
+                writer.write_record([
+                    user.user.clone(),
+                    user.hop.to_string(),
+                    user.seed_count.to_string(),
+                    user.page_count.to_string(),
+                    user.seed_score.to_string(),
+                    user.page_score.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => return Err(graph_format_error()),
+    }
+    Ok(())
+}
+
+// Communities given to the users in one line or row per user: their community,
+// its density, and for the bridges the other communities they are linked to
+fn user_communities(communities: &Communities) -> Vec<(&str, usize, f64, Vec<usize>, f64)> {
+    let mut users = vec![];
+    for (position, community) in communities.communities().iter().enumerate() {
+        for user in &community.users {
+            let (linked_communities, participation) = match communities.bridges().iter().find(|bridge| bridge.user == *user) {
+                Some(bridge) => (bridge.linked_communities.clone(), bridge.participation),
+                None => (vec![], 0.),
+            };
+            users.push((user.as_str(), position + 1, community.density, linked_communities, participation));
+        }
+    }
+    users
+}
+
+fn community_list(communities: &[usize]) -> String {
+    communities.iter().map(|community| community.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// Write communities of users in any format
+pub(crate) fn write_communities(writer: &mut dyn Write, format: Format, communities: &Communities, namespaces: &BTreeMap<i32, String>) -> std::io::Result<()> {
+    match format {
+        Format::text => {
+            for (position, community) in communities.communities().iter().enumerate() {
+                writeln!(writer, "Community {}: {} (density {:.3})", position + 1, community.users.join(", "), community.density)?;
+            }
+            if !communities.bridges().is_empty() {
+                writeln!(writer, "Bridges:")?;
+                for bridge in communities.bridges() {
+                    writeln!(writer, "{}: community {}, linked to {} (participation {:.3})", bridge.user, bridge.community, community_list(&bridge.linked_communities), bridge.participation)?;
+                }
+            }
+            writeln!(writer, "Modularity: {:.3}", communities.modularity())?;
+        },
+        Format::json => {
+            let object = serde_json::json!({
+                "communities": communities.communities(),
+                "bridges": communities.bridges(),
+                "modularity": communities.modularity(),
+            });
+            serde_json::to_writer(&mut *writer, &object)?;
+            writeln!(writer)?;
+        },
+        // one line per user
+        Format::jsonl => {
+            for (user, community, density, linked_communities, participation) in user_communities(communities) {
+                let object = serde_json::json!({
+                    "user": user,
+                    "community": community,
+                    "community_density": density,
+                    "linked_communities": linked_communities,
+                    "participation": participation,
+                });
+                serde_json::to_writer(&mut *writer, &object)?;
+                writeln!(writer)?;
+            }
+        },
+        Format::wikitext => {
+            let contributions_prefix = contributions_prefix(namespaces);
+            writeln!(writer, "{{| class=\"wikitable\"")?;
+            writeln!(writer, "! Community !! Editors !! Density")?;
+            for (position, community) in communities.communities().iter().enumerate() {
+                let links: Vec<_> = community.users.iter()
+                    .map(|user| user_link(&contributions_prefix, user))
+                    .collect();
+                writeln!(writer, "|-")?;
+                writeln!(writer, "| {} || {} || {:.3}", position + 1, links.join(", "), community.density)?;
+            }
+            writeln!(writer, "|}}")?;
+            if !communities.bridges().is_empty() {
+                writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+                writeln!(writer, "! Bridge !! Community !! Linked communities !! Participation")?;
+                for bridge in communities.bridges() {
+                    writeln!(writer, "|-")?;
+                    writeln!(writer, "| {} || {} || {} || {:.3}", user_link(&contributions_prefix, &bridge.user), bridge.community, community_list(&bridge.linked_communities), bridge.participation)?;
+                }
+                writeln!(writer, "|}}")?;
+            }
+        },
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            writer.write_record(["user", "community", "community_density", "linked_communities", "participation"])?;
+            for (user, community, density, linked_communities, participation) in user_communities(communities) {
+                writer.write_record([
+                    user.to_string(),
+                    community.to_string(),
+                    density.to_string(),
+                    linked_communities.iter().map(|community| community.to_string()).collect::<Vec<_>>().join(" "),
+                    participation.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => return Err(graph_format_error()),
+    }
+    Ok(())
+}
+
+// Time between two revisions, eg. 00:05:00 or 3d 04:00:00
+fn delay(seconds: u64) -> String {
+    let time = format!("{:02}:{:02}:{:02}", seconds % 86400 / 3600, seconds / 60 % 60, seconds % 60);
+    if seconds >= 86400 {
+        format!("{}d {}", seconds / 86400, time)
+    } else {
+        time
+    }
+}
+
+// Write the pages on which a user has followed another one in any format;
+// delays are in seconds except in text and wikitext
+pub(crate) fn write_followed_pages(writer: &mut dyn Write, format: Format, pages: &[FollowedPage]) -> std::io::Result<()> {
+    match format {
+        Format::text => {
+            for page in pages {
+                writeln!(writer, "{}: {} (shortest {}, average {})", page.title, page.count, delay(page.shortest_delay as u64), delay(page.average_delay))?;
+            }
+        },
+        Format::json => {
+            serde_json::to_writer(&mut *writer, pages)?;
+            writeln!(writer)?;
+        },
+        Format::jsonl => {
+            for page in pages {
+                serde_json::to_writer(&mut *writer, page)?;
+                writeln!(writer)?;
+            }
+        },
+        Format::wikitext => {
+            writeln!(writer, "{{| class=\"wikitable sortable\"")?;
+            writeln!(writer, "! Page !! Count !! Shortest delay !! Average delay")?;
+            for page in pages {
+                writeln!(writer, "|-")?;
+                writeln!(writer, "| [[:{}]] || {} || {} || {}", page.title, page.count, delay(page.shortest_delay as u64), delay(page.average_delay))?;
+            }
+            writeln!(writer, "|}}")?;
+        },
+        Format::csv | Format::tsv => {
+            let mut writer = delimited_writer(writer, format);
+            writer.write_record(["title", "namespace", "count", "shortest_delay", "average_delay"])?;
+            for page in pages {
+                writer.write_record([
+                    page.title.clone(),
+                    page.namespace.to_string(),
+                    page.count.to_string(),
+                    page.shortest_delay.to_string(),
+                    page.average_delay.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+        },
+        Format::dot | Format::graphml | Format::gexf => return Err(graph_format_error()),
+    }
+    Ok(())
+}
@@ -0,0 +1,1536 @@
+use serde_derive::Serialize;
+
+use byteorder::ReadBytesExt;
+
+use std::{
+    cmp::{
+        Ordering,
+        Reverse,
+    },
+    collections::{
+        BinaryHeap,
+        BTreeMap,
+        BTreeSet,
+        HashMap,
+        HashSet,
+    },
+    fs::File,
+    io::{
+        BufRead,
+        Cursor,
+        Seek,
+        SeekFrom,
+    },
+    path::Path,
+    sync::{
+        Arc,
+        OnceLock,
+    },
+};
+
+use crate::{
+    adjacency::Adjacency,
+    check_fst,
+    community,
+    read_index_header,
+    page_names_end_offset,
+    read_metadata,
+    read_page_counts,
+    read_namespaces,
+    read_page,
+    read_page_offsets,
+    read_user_postings,
+    resolve_namespace,
+    signatures::{
+        equal_value_count,
+        Signatures,
+    },
+    Error,
+    Expression,
+    Filter,
+    IndexHeader,
+    Metadata,
+    Metric,
+    PageFilter,
+    Order,
+    Section,
+    SF_SECTION_ADJACENCY,
+    SF_SECTION_EDIT_COUNTS,
+    SF_SECTION_PAGE_EDITORS,
+    SF_SECTION_SIGNATURES,
+    SF_SECTION_TITLES,
+    SF_SECTION_USERS,
+    SF_VERSION_WITHOUT_EDITOR_COUNTS,
+    SF_VERSION_WITHOUT_NAMESPACES,
+    SF_VERSION_WITHOUT_PAGE_EDITORS,
+    SF_VERSION_WITHOUT_TIMESTAMPS,
+};
+
+enum IndexBytes {
+    Mapped(memmap2::Mmap),
+    Loaded(Vec<u8>),
+}
+
+impl AsRef<[u8]> for IndexBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            IndexBytes::Mapped(mmap) => mmap,
+            IndexBytes::Loaded(bytes) => bytes,
+        }
+    }
+}
+
+// Part of the bytes of an index, shared with the handle instead of copied
+#[derive(Clone)]
+pub(crate) struct SectionBytes {
+    bytes: Arc<IndexBytes>,
+    start: usize,
+    end: usize,
+}
+
+impl AsRef<[u8]> for SectionBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes.as_ref().as_ref()[self.start..self.end]
+    }
+}
+
+/// Index opened once to answer any number of queries, either mapped in
+/// memory from its file or loaded in memory by the caller. The FSTs and the
+/// namespaces are only read when the index is opened.
+pub struct IndexReader {
+    bytes: Arc<IndexBytes>,
+    pub(crate) header: IndexHeader,
+    pub(crate) fst: fst::Map<SectionBytes>,
+    // None for indexes in format versions without page editors
+    titles: Option<fst::Map<SectionBytes>>,
+    pub(crate) namespaces: BTreeMap<i32, String>,
+    pub(crate) metadata: Option<Metadata>,
+    page_counts: OnceLock<BTreeMap<(i32, Option<u32>), u64>>,
+    // offsets of the entries of the page editors section, in the order of
+    // the page records, read the first time they are looked up by page
+    entry_offsets: OnceLock<Vec<u64>>,
+}
+
+impl IndexReader {
+    /// Map the index file in memory. The file must not be modified as long
+    /// as the handle is alive (replacing it with another file is fine).
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::Io)?;
+        // safe as long as the file isn't modified, see above
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(Error::Io)?;
+        IndexReader::new(IndexBytes::Mapped(mmap))
+    }
+
+    /// Use an index already loaded in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        IndexReader::new(IndexBytes::Loaded(bytes))
+    }
+
+    fn new(bytes: IndexBytes) -> Result<Self, Error> {
+        let bytes = Arc::new(bytes);
+        let mut cursor = Cursor::new(bytes.as_ref().as_ref());
+        let header = read_index_header(&mut cursor)?;
+        let namespaces = read_namespaces(&mut cursor, &header)?;
+        let metadata = read_metadata(&mut cursor, &header)?;
+        let section_bytes = |section: Section| SectionBytes {
+            bytes: bytes.clone(),
+            start: section.offset as usize,
+            end: (section.offset + section.length) as usize,
+        };
+        let fst = check_fst(&header, fst::Map::new(section_bytes(header.fst_section()))?)?;
+        let titles = match header.section(SF_SECTION_TITLES) {
+            Some(section) if header.version > SF_VERSION_WITHOUT_PAGE_EDITORS => Some(check_fst(&header, fst::Map::new(section_bytes(section))?)?),
+            _ => None,
+        };
+        Ok(IndexReader {
+            bytes,
+            header,
+            fst,
+            titles,
+            namespaces,
+            metadata,
+            page_counts: OnceLock::new(),
+            entry_offsets: OnceLock::new(),
+        })
+    }
+
+    // Reader on the whole index, to read page names and postings without copying them
+    pub(crate) fn cursor(&self) -> Cursor<&[u8]> {
+        Cursor::new(self.bytes.as_ref().as_ref())
+    }
+
+    /// Metadata of the index, if it has been built by a version of
+    /// socksfinder recent enough to record them
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Namespaces of the wiki, by key (the main namespace has an empty name)
+    pub fn namespaces(&self) -> &BTreeMap<i32, String> {
+        &self.namespaces
+    }
+
+    /// Number of users (or IPs) in the index
+    pub fn user_count(&self) -> usize {
+        self.fst.len()
+    }
+
+    /// Number of pages considered by the filter (its dates are ignored, as
+    /// pages have no creation time in the index). The page names are read
+    /// the first time pages are filtered, unless the index has no metadata.
+    pub fn page_count(&self, filter: &Filter) -> Result<u64, Error> {
+        let page_filter = self.page_filter(filter)?;
+        if let (None, Some(metadata)) = (&page_filter, &self.metadata) {
+            return Ok(metadata.page_count);
+        }
+        let page_counts = match self.page_counts.get() {
+            Some(page_counts) => page_counts,
+            None => {
+                let page_names_end_offset = page_names_end_offset(&self.header, &self.fst)?;
+                let page_counts = read_page_counts(&mut self.cursor(), &self.header, page_names_end_offset)?;
+                self.page_counts.get_or_init(|| page_counts)
+            },
+        };
+        Ok(page_counts.iter()
+            .filter(|((namespace, editor_count), _)| page_filter.as_ref().is_none_or(|page_filter| page_filter.accepts(*namespace, *editor_count)))
+            .map(|(_, page_count)| page_count)
+            .sum())
+    }
+
+    fn page_filter(&self, filter: &Filter) -> Result<Option<PageFilter>, Error> {
+        if self.header.version <= SF_VERSION_WITHOUT_TIMESTAMPS &&
+           (filter.since.is_some() || filter.until.is_some()) {
+            return Err(Error::OutdatedIndex(format!("index in format version {} has no edit timestamps, rebuild it to search by date", self.header.version)));
+        }
+        if self.header.version <= SF_VERSION_WITHOUT_EDITOR_COUNTS && filter.max_page_editors.is_some() {
+            return Err(Error::OutdatedIndex(format!("index in format version {} has no page editor counts, rebuild it to filter pages by editor count", self.header.version)));
+        }
+        if filter.namespaces.is_empty() && filter.excluded_namespaces.is_empty() && filter.max_page_editors.is_none() {
+            return Ok(None);
+        }
+        if self.header.version <= SF_VERSION_WITHOUT_NAMESPACES &&
+           (!filter.namespaces.is_empty() || !filter.excluded_namespaces.is_empty()) {
+            return Err(Error::OutdatedIndex(format!("index in format version {} has no page namespaces, rebuild it to search by namespace", self.header.version)));
+        }
+        let resolve = |names: &Vec<String>| {
+            let mut keys = HashSet::with_capacity(names.len());
+            for name in names {
+                match resolve_namespace(&self.namespaces, name) {
+                    Some(key) => keys.insert(key),
+                    None => return Err(Error::InvalidQuery(format!("namespace '{}' does not exist", name))),
+                };
+            }
+            Ok(keys)
+        };
+        Ok(Some(PageFilter {
+            included: resolve(&filter.namespaces)?,
+            excluded: resolve(&filter.excluded_namespaces)?,
+            max_editor_count: filter.max_page_editors,
+        }))
+    }
+
+    fn matches(&self, users: &[String], threshold: usize, filter: &Filter, read_titles: bool) -> Result<PageMatches<'_>, Error> {
+        let page_filter = self.page_filter(filter)?;
+        let users = unique_names(users);
+        let threshold = if threshold == 0 {
+            users.len()
+        } else {
+            threshold
+        };
+        let mut cursor = self.cursor();
+        let mut lists = vec![];
+        let mut unknown_users = vec![];
+        let mut heap = BinaryHeap::with_capacity(users.len());
+        for (user_index, user) in users.iter().enumerate() {
+            match self.fst.get(user) {
+                None => unknown_users.push(user.clone()),
+                Some(value) => {
+                    let page_offsets = read_page_offsets(&mut cursor, &self.header, value, filter)?;
+                    if page_offsets.is_empty() {
+                        continue;
+                    }
+                    heap.push(Reverse(page_offsets[0]));
+                    lists.push(InvertedList {
+                        user: user_index,
+                        position: 0,
+                        page_offsets,
+                    });
+                }
+            }
+        }
+        Ok(PageMatches {
+            reader: self,
+            cursor,
+            list_count: lists.len(),
+            users,
+            lists,
+            heap,
+            threshold,
+            page_filter,
+            expression: None,
+            read_titles,
+            unknown_users,
+        })
+    }
+
+    /// Search pages modified by at least threshold of the users (or by all
+    /// of them if threshold is 0), in the order of the index. Unknown users
+    /// are skipped, see [`PageMatches::unknown_users`].
+    pub fn pages(&self, users: &[String], threshold: usize, filter: &Filter) -> Result<PageMatches<'_>, Error> {
+        self.matches(users, threshold, filter, true)
+    }
+
+    /// Search pages matching the expression, in the order of the index. The
+    /// editors of the pages are the users of the expression who have
+    /// modified them.
+    pub fn pages_matching(&self, expression: &Expression, filter: &Filter) -> Result<PageMatches<'_>, Error> {
+        let mut matches = self.matches(&expression.users(), expression.required_user_count().max(1), filter, true)?;
+        matches.expression = Some(expression.clone());
+        Ok(matches)
+    }
+
+    /// Count the pages modified by each pair of users, and by each of them
+    pub fn cooccurrences(&self, users: &[String], filter: &Filter) -> Result<CooccurrenceMatrix, Error> {
+        let mut matches = self.matches(users, 2, filter, false)?;
+        // when pages are filtered, the pages modified by only one of the
+        // users have to be read as well to know how many pages each user has
+        let pages_filtered = matches.page_filter.is_some();
+        if pages_filtered {
+            matches.threshold = 1;
+        }
+        let user_count = matches.users.len();
+        let mut counts = vec![0; user_count * user_count];
+        let mut page_counts = vec![0; user_count];
+        if !pages_filtered {
+            for list in &matches.lists {
+                page_counts[list.user] = list.page_offsets.len() as u64;
+            }
+        }
+        while let Some(RawMatch { editors, .. }) = matches.next_match()? {
+            if pages_filtered {
+                for editor in &editors {
+                    page_counts[*editor] += 1;
+                }
+            }
+            for first_editor in &editors {
+                for second_editor in &editors {
+                    if first_editor != second_editor {
+                        counts[first_editor * user_count + second_editor] += 1;
+                    }
+                }
+            }
+        }
+        let edit_counts = matches.users.iter()
+            .map(|user| self.edit_count(user))
+            .collect::<Result<_, _>>()?;
+        Ok(CooccurrenceMatrix {
+            users: matches.users,
+            counts,
+            page_counts,
+            edit_counts,
+            total_page_count: self.page_count(filter)?,
+            unknown_users: matches.unknown_users,
+        })
+    }
+
+    /// Get the number of revisions of a user in the whole index, or None if
+    /// the user does not exist or the index has no edit counts (indexes built
+    /// before they were added, or updated from such an index)
+    pub fn edit_count(&self, user: &str) -> Result<Option<u64>, Error> {
+        let section = match self.header.section(SF_SECTION_EDIT_COUNTS) {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        if section.length != 8 * self.fst.len() as u64 {
+            return Err(Error::Corrupt(format!("the edit counts section is {} bytes long instead of {} for {} users", section.length, 8 * self.fst.len(), self.fst.len())));
+        }
+        let mut cursor = self.cursor();
+        let user_number = match self.user_number(&mut cursor, user.replace("_", " ").trim())? {
+            Some(user_number) => user_number,
+            None => return Ok(None),
+        };
+        cursor.seek(SeekFrom::Start(section.offset + 8 * user_number as u64)).map_err(Error::from_index)?;
+        Ok(Some(cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?))
+    }
+
+    /// Get the pages modified by a user, in the order of the index, or None
+    /// if the user does not exist or has no edits
+    pub fn user_pages(&self, user: &str, filter: &Filter) -> Result<Option<Vec<UserPage>>, Error> {
+        let page_filter = self.page_filter(filter)?;
+        let value = match self.fst.get(user.replace("_", " ").trim()) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut cursor = self.cursor();
+        let mut pages = vec![];
+        for posting in read_user_postings(&mut cursor, &self.header, value, filter)? {
+            let mut title = String::new();
+            let (namespace, editor_count) = read_page(&mut cursor, &self.header, posting.page_offset, &mut title)?;
+            if page_filter.as_ref().is_none_or(|page_filter| page_filter.accepts(namespace, editor_count)) {
+                let has_timestamps = self.header.version > SF_VERSION_WITHOUT_TIMESTAMPS;
+                pages.push(UserPage {
+                    title,
+                    namespace,
+                    first_edit: Some(posting.first_edit).filter(|_| has_timestamps),
+                    last_edit: Some(posting.last_edit).filter(|_| has_timestamps),
+                });
+            }
+        }
+        Ok(Some(pages))
+    }
+
+    /// Get the editors of a page, in alphabetical order, or None if the page
+    /// does not exist
+    pub fn page_editors(&self, title: &str) -> Result<Option<Vec<String>>, Error> {
+        let titles = self.titles()?;
+        let entry_offset = match titles.get(title.replace("_", " ").trim()) {
+            Some(entry_offset) => entry_offset,
+            None => return Ok(None),
+        };
+        let mut cursor = self.cursor();
+        let mut editors = vec![];
+        for user_number in self.read_page_editors(&mut cursor, entry_offset)? {
+            editors.push(self.read_user(&mut cursor, user_number)?);
+        }
+        Ok(Some(editors))
+    }
+
+    /// Search users who have modified at least threshold of the pages (or
+    /// all of them if threshold is 0), in alphabetical order. Unknown pages
+    /// are skipped, see [`EditorMatches::unknown_pages`].
+    pub fn editors(&self, titles: &[String], threshold: usize) -> Result<EditorMatches, Error> {
+        let title_fst = self.titles()?;
+        let titles = unique_names(titles);
+        let threshold = if threshold == 0 {
+            titles.len()
+        } else {
+            threshold
+        };
+        let mut cursor = self.cursor();
+        let mut user_pages: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        let mut unknown_pages = vec![];
+        for (title_index, title) in titles.iter().enumerate() {
+            match title_fst.get(title) {
+                None => unknown_pages.push(title.clone()),
+                Some(entry_offset) => for user_number in self.read_page_editors(&mut cursor, entry_offset)? {
+                    user_pages.entry(user_number).or_default().push(title_index);
+                },
+            }
+        }
+        let mut editors = vec![];
+        for (user_number, pages) in user_pages {
+            if pages.len() >= threshold {
+                editors.push(EditorMatch {
+                    user: self.read_user(&mut cursor, user_number)?,
+                    page_count: pages.len(),
+                    pages: pages.iter().map(|page| titles[*page].clone()).collect(),
+                });
+            }
+        }
+        Ok(EditorMatches {
+            editors,
+            unknown_pages,
+        })
+    }
+
+    /// Search users who have modified some of the pages modified by a user,
+    /// by decreasing similarity with them according to the metric, or None
+    /// if the user does not exist or has no edits. The excluded users (eg.
+    /// bots) are skipped.
+    pub fn similar_users(&self, user: &str, filter: &Filter, excluded_users: &[String], metric: Metric) -> Result<Option<Vec<SimilarUser>>, Error> {
+        // only indexes with page editors can tell the editors of the pages
+        self.titles()?;
+        let page_filter = self.page_filter(filter)?;
+        let user = user.replace("_", " ").trim().to_string();
+        let value = match self.fst.get(&user) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut cursor = self.cursor();
+        let (user_pages, mut accepted_pages) = self.filtered_pages(&mut cursor, value, filter, &page_filter)?;
+        let mut candidates = BTreeSet::new();
+        for page_offset in &user_pages {
+            candidates.extend(self.read_page_editors_of(&mut cursor, *page_offset)?);
+        }
+        let excluded_users = normalized_names(excluded_users);
+        let total_page_count = self.page_count(filter)?;
+        let mut similar_users = vec![];
+        for user_number in candidates {
+            let candidate = self.read_user(&mut cursor, user_number)?;
+            if candidate == user || excluded_users.contains(&candidate) {
+                continue;
+            }
+            let (count, page_count) = self.shared_page_counts(&mut cursor, &candidate, filter, &page_filter, &user_pages, &mut accepted_pages)?;
+            // the pages in common may all be outside of the dates
+            if count > 0 {
+                similar_users.push(SimilarUser {
+                    similarity: similarity(count, user_pages.len() as u64, page_count, total_page_count, metric),
+                    user: candidate,
+                    count,
+                    page_count,
+                    estimated_jaccard: None,
+                });
+            }
+        }
+        // stable, so that users equally similar stay in alphabetical order
+        similar_users.sort_by(|first_user, second_user| second_user.similarity.total_cmp(&first_user.similarity)
+            .then(second_user.count.cmp(&first_user.count)));
+        Ok(Some(similar_users))
+    }
+
+    /// Search users who have modified pages of the seeds (eg. confirmed
+    /// sockpuppets), by decreasing number of seeds whose pages they have
+    /// modified, then of such pages. With more than one hop, the top users
+    /// found at each hop (all of them if top is 0) are seeds of the next one,
+    /// weighing decay times as much as the seeds they have been found from.
+    /// Unknown seeds are skipped, see [`Expansion::unknown_users`], and so
+    /// are the excluded users (eg. bots).
+    pub fn expand(&self, seeds: &[String], filter: &Filter, excluded_users: &[String], hops: usize, decay: f64, top: usize) -> Result<Expansion, Error> {
+        let titles = self.titles()?;
+        let excluded_users = normalized_names(excluded_users);
+        let mut seeds: Vec<(String, f64)> = unique_names(seeds).into_iter().map(|seed| (seed, 1.)).collect();
+        let mut known_users: HashSet<String> = seeds.iter().map(|(seed, _)| seed.clone()).collect();
+        let mut cursor = self.cursor();
+        let mut users = vec![];
+        let mut unknown_users = vec![];
+        for hop in 1..=hops {
+            let seed_names: Vec<String> = seeds.iter().map(|(seed, _)| seed.clone()).collect();
+            let mut matches = self.matches(&seed_names, 1, filter, true)?;
+            if hop == 1 {
+                unknown_users = matches.unknown_users.clone();
+            }
+            // seeds whose pages each user has modified, and weight of these pages
+            let mut scores: BTreeMap<u32, (HashSet<usize>, usize, f64)> = BTreeMap::new();
+            while let Some(RawMatch { title, editors, .. }) = matches.next_match()? {
+                let entry_offset = match titles.get(&title) {
+                    Some(entry_offset) => entry_offset,
+                    None => continue,
+                };
+                let page_weight = editors.iter().map(|editor| seeds[*editor].1).fold(0., f64::max);
+                for user_number in self.read_page_editors(&mut cursor, entry_offset)? {
+                    let (user_seeds, page_count, page_score) = scores.entry(user_number).or_default();
+                    user_seeds.extend(&editors);
+                    *page_count += 1;
+                    *page_score += page_weight;
+                }
+            }
+            let mut hop_users = vec![];
+            for (user_number, (user_seeds, page_count, page_score)) in scores {
+                let user = self.read_user(&mut cursor, user_number)?;
+                if !known_users.contains(&user) && !excluded_users.contains(&user) {
+                    hop_users.push(ExpandedUser {
+                        user,
+                        hop,
+                        seed_count: user_seeds.len(),
+                        page_count,
+                        seed_score: user_seeds.iter().map(|seed| seeds[*seed].1).sum(),
+                        page_score,
+                    });
+                }
+            }
+            // stable, so that users with the same scores stay in alphabetical order
+            hop_users.sort_by(|first_user, second_user| second_user.seed_score.total_cmp(&first_user.seed_score)
+                .then(second_user.page_score.total_cmp(&first_user.page_score)));
+            if top > 0 {
+                hop_users.truncate(top);
+            }
+            for user in &hop_users {
+                known_users.insert(user.user.clone());
+                seeds.push((user.user.clone(), decay.powi(hop as i32)));
+            }
+            users.extend(hop_users);
+        }
+        Ok(Expansion {
+            users,
+            unknown_users,
+        })
+    }
+
+    // Compute exactly the similarity of the candidates found by signature
+    // with the user, whose pages are only read once, most similar first
+    pub(crate) fn similar_estimated_users(&self, user: &str, candidates: Vec<SimilarCandidate>, filter: &Filter, metric: Metric) -> Result<Vec<SimilarUser>, Error> {
+        let page_filter = self.page_filter(filter)?;
+        let value = match self.fst.get(user.replace("_", " ").trim()) {
+            Some(value) => value,
+            None => return Err(Error::UnknownUsers(vec![user.to_string()])),
+        };
+        let mut cursor = self.cursor();
+        let (user_pages, mut accepted_pages) = self.filtered_pages(&mut cursor, value, filter, &page_filter)?;
+        let total_page_count = self.page_count(filter)?;
+        let mut similar_users = vec![];
+        for candidate in candidates {
+            let (count, page_count) = self.shared_page_counts(&mut cursor, &candidate.user, filter, &page_filter, &user_pages, &mut accepted_pages)?;
+            // the pages in common may all be filtered out
+            if count > 0 {
+                similar_users.push(SimilarUser {
+                    similarity: similarity(count, user_pages.len() as u64, page_count, total_page_count, metric),
+                    user: candidate.user,
+                    count,
+                    page_count,
+                    estimated_jaccard: Some(candidate.estimated_jaccard),
+                });
+            }
+        }
+        similar_users.sort_by(|first_user, second_user| second_user.similarity.total_cmp(&first_user.similarity));
+        Ok(similar_users)
+    }
+
+    // Pages of a user which pass the filter, along with whether each of its
+    // pages passes the filter, which is kept so that the pages of the user
+    // aren't read again when they are also pages of the candidates
+    fn filtered_pages(&self, cursor: &mut Cursor<&[u8]>, value: u64, filter: &Filter, page_filter: &Option<PageFilter>) -> Result<(HashSet<u64>, HashMap<u64, bool>), Error> {
+        let mut user_pages = HashSet::new();
+        let mut accepted_pages = HashMap::new();
+        let mut title = String::new();
+        for page_offset in read_page_offsets(cursor, &self.header, value, filter)? {
+            let accepted = match page_filter {
+                Some(page_filter) => {
+                    title.clear();
+                    let (namespace, editor_count) = read_page(cursor, &self.header, page_offset, &mut title)?;
+                    page_filter.accepts(namespace, editor_count)
+                },
+                None => true,
+            };
+            if accepted {
+                user_pages.insert(page_offset);
+            }
+            accepted_pages.insert(page_offset, accepted);
+        }
+        Ok((user_pages, accepted_pages))
+    }
+
+    // Number of pages of a candidate which are also pages of the user, and
+    // number of pages of the candidate, among those which pass the filter;
+    // whether pages pass the filter is kept to read each of them only once
+    fn shared_page_counts(&self, cursor: &mut Cursor<&[u8]>, candidate: &str, filter: &Filter, page_filter: &Option<PageFilter>, user_pages: &HashSet<u64>, accepted_pages: &mut HashMap<u64, bool>) -> Result<(u64, u64), Error> {
+        let value = match self.fst.get(candidate) {
+            Some(value) => value,
+            None => return Err(Error::Corrupt(format!("user '{}' is missing from the users of the index", candidate))),
+        };
+        let mut count = 0;
+        let mut page_count = 0;
+        let mut title = String::new();
+        for page_offset in read_page_offsets(cursor, &self.header, value, filter)? {
+            let accepted = match (page_filter, accepted_pages.get(&page_offset)) {
+                (None, _) => true,
+                (Some(_), Some(accepted)) => *accepted,
+                (Some(page_filter), None) => {
+                    title.clear();
+                    let (namespace, editor_count) = read_page(cursor, &self.header, page_offset, &mut title)?;
+                    let accepted = page_filter.accepts(namespace, editor_count);
+                    accepted_pages.insert(page_offset, accepted);
+                    accepted
+                },
+            };
+            if accepted {
+                page_count += 1;
+                if user_pages.contains(&page_offset) {
+                    count += 1;
+                }
+            }
+        }
+        Ok((count, page_count))
+    }
+
+    /// Search users whose MinHash signature is close to the one of a user, by
+    /// decreasing estimated Jaccard index, or None if the user does not exist
+    /// or has no edits. Only the top candidates are given (all of them if top
+    /// is 0). This only takes a few lookups, but misses users who are not
+    /// similar enough (see [`crate::BuildOptions::signatures`]) as well as
+    /// users who share their signature with too many others (eg. IPs which
+    /// have only modified a popular page), and the estimates are to be
+    /// checked against the pages of the users. The excluded users (eg. bots)
+    /// are skipped.
+    pub fn similar_candidates(&self, user: &str, excluded_users: &[String], top: usize) -> Result<Option<Vec<SimilarCandidate>>, Error> {
+        let section = match self.header.section(SF_SECTION_SIGNATURES) {
+            Some(section) => section,
+            None => return Err(Error::OutdatedIndex("index has no user signatures, rebuild it with --signatures to estimate the similarity of users".to_string())),
+        };
+        let mut cursor = self.cursor();
+        let signatures = Signatures::read(&mut cursor, section, self.fst.len() as u64)?;
+        let user = user.replace("_", " ").trim().to_string();
+        let user_number = match self.user_number(&mut cursor, &user)? {
+            Some(user_number) => user_number,
+            None => return Ok(None),
+        };
+        let signature = signatures.signature(&mut cursor, user_number)?;
+        let mut excluded_user_numbers = HashSet::new();
+        for excluded_user in normalized_names(excluded_users) {
+            if let Some(excluded_user_number) = self.user_number(&mut cursor, &excluded_user)? {
+                excluded_user_numbers.insert(excluded_user_number);
+            }
+        }
+        // the top candidates so far, the least similar on top so that it
+        // can be replaced; users with the same estimate are in alphabetical
+        // order, which is the order of their numbers
+        let mut top_candidates = BinaryHeap::new();
+        for candidate_number in signatures.candidates(&mut cursor, &signature)? {
+            if candidate_number == user_number || excluded_user_numbers.contains(&candidate_number) {
+                continue;
+            }
+            let equal_value_count = equal_value_count(&signature, &signatures.signature(&mut cursor, candidate_number)?);
+            top_candidates.push(Reverse((equal_value_count, Reverse(candidate_number))));
+            if top > 0 && top_candidates.len() > top {
+                top_candidates.pop();
+            }
+        }
+        let mut candidates = Vec::with_capacity(top_candidates.len());
+        for Reverse((equal_value_count, Reverse(candidate_number))) in top_candidates.into_sorted_vec() {
+            candidates.push(SimilarCandidate {
+                user: self.read_user(&mut cursor, candidate_number)?,
+                estimated_jaccard: equal_value_count as f64 / signature.len() as f64,
+            });
+        }
+        Ok(Some(candidates))
+    }
+
+    /// Search the pages on which revisions of a user have directly followed
+    /// revisions of another user, in the order of the pages. Only the filters
+    /// on pages apply, as the times of the revisions aren't in the index.
+    pub fn follows(&self, user: &str, followed_user: &str, filter: &Filter) -> Result<Follows, Error> {
+        let section = match self.header.section(SF_SECTION_ADJACENCY) {
+            Some(section) => section,
+            None => return Err(Error::OutdatedIndex("index has no revision adjacency, rebuild it with --adjacency to search users following others".to_string())),
+        };
+        if filter.since.is_some() || filter.until.is_some() {
+            return Err(Error::InvalidQuery("the revision adjacency can't be searched by date".to_string()));
+        }
+        let page_filter = self.page_filter(filter)?;
+        let adjacency = Adjacency::read(section, self.fst.len() as u64)?;
+        let mut cursor = self.cursor();
+        let mut user_numbers = vec![];
+        let mut unknown_users = vec![];
+        for user in [user, followed_user] {
+            match self.user_number(&mut cursor, user.replace("_", " ").trim())? {
+                Some(user_number) => user_numbers.push(user_number),
+                None => unknown_users.push(user.to_string()),
+            }
+        }
+        let mut pages = vec![];
+        if let [follower, followed] = user_numbers[..] {
+            for follow in adjacency.follows_of(&mut cursor, follower, followed)? {
+                let mut title = String::new();
+                let (namespace, editor_count) = read_page(&mut cursor, &self.header, follow.page_offset, &mut title)?;
+                if page_filter.as_ref().is_none_or(|page_filter| page_filter.accepts(namespace, editor_count)) {
+                    pages.push(FollowedPage {
+                        title,
+                        namespace,
+                        count: follow.delays.count,
+                        shortest_delay: follow.delays.shortest,
+                        average_delay: follow.delays.total / follow.delays.count.max(1) as u64,
+                    });
+                }
+            }
+        }
+        Ok(Follows {
+            pages,
+            unknown_users,
+        })
+    }
+
+    // Number of a user, found by dichotomy in the users section as they are
+    // sorted like in the FST
+    fn user_number(&self, cursor: &mut Cursor<&[u8]>, user: &str) -> Result<Option<u32>, Error> {
+        if !self.fst.contains_key(user) {
+            return Ok(None);
+        }
+        let (mut low, mut high) = (0, self.fst.len() as u32);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.read_user(cursor, middle)?.as_str().cmp(user) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Ok(Some(middle)),
+            }
+        }
+        Err(Error::Corrupt(format!("user '{}' is missing from the users section", user)))
+    }
+
+    fn titles(&self) -> Result<&fst::Map<SectionBytes>, Error> {
+        match &self.titles {
+            Some(titles) => Ok(titles),
+            None => Err(Error::OutdatedIndex(format!("index in format version {} has no page editors, rebuild it to search editors of pages", self.header.version))),
+        }
+    }
+
+    // Read the numbers of the editors of a page from its entry in the page
+    // editors section
+    fn read_page_editors(&self, cursor: &mut Cursor<&[u8]>, entry_offset: u64) -> Result<Vec<u32>, Error> {
+        let section = self.section(SF_SECTION_PAGE_EDITORS)?;
+        if entry_offset < section.offset || entry_offset + 12 > section.offset + section.length {
+            return Err(Error::Corrupt(format!("page editors at offset {} are outside of their section", entry_offset)));
+        }
+        cursor.seek(SeekFrom::Start(entry_offset + 8)).map_err(Error::from_index)?;
+        let editor_count = cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)? as u64;
+        if entry_offset + 12 + 4 * editor_count > section.offset + section.length {
+            return Err(Error::Corrupt(format!("{} page editors at offset {} overflow their section", editor_count, entry_offset)));
+        }
+        let mut editors = Vec::with_capacity(editor_count as usize);
+        for _ in 0..editor_count {
+            editors.push(cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)?);
+        }
+        Ok(editors)
+    }
+
+    // Read the numbers of the editors of a page given the offset of its
+    // record, finding its entry by dichotomy as the entries are in the order
+    // of the page records, since several pages can have the same title
+    fn read_page_editors_of(&self, cursor: &mut Cursor<&[u8]>, page_offset: u64) -> Result<Vec<u32>, Error> {
+        let entry_offsets = match self.entry_offsets.get() {
+            Some(entry_offsets) => entry_offsets,
+            None => {
+                let entry_offsets = self.read_entry_offsets(cursor)?;
+                self.entry_offsets.get_or_init(|| entry_offsets)
+            },
+        };
+        let mut low = 0;
+        let mut high = entry_offsets.len();
+        while low < high {
+            let middle = low + (high - low) / 2;
+            cursor.seek(SeekFrom::Start(entry_offsets[middle])).map_err(Error::from_index)?;
+            match cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?.cmp(&page_offset) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return self.read_page_editors(cursor, entry_offsets[middle]),
+            }
+        }
+        Err(Error::Corrupt(format!("page at offset {} has no entry in the page editors section", page_offset)))
+    }
+
+    // Offsets of the entries of the page editors section, each of them being
+    // the offset of the page record, the number of editors and their numbers
+    fn read_entry_offsets(&self, cursor: &mut Cursor<&[u8]>) -> Result<Vec<u64>, Error> {
+        let section = self.section(SF_SECTION_PAGE_EDITORS)?;
+        let mut entry_offsets = vec![];
+        let mut entry_offset = section.offset;
+        while entry_offset < section.offset + section.length {
+            if entry_offset + 12 > section.offset + section.length {
+                return Err(Error::Corrupt(format!("page editors at offset {} are outside of their section", entry_offset)));
+            }
+            cursor.seek(SeekFrom::Start(entry_offset + 8)).map_err(Error::from_index)?;
+            let editor_count = cursor.read_u32::<byteorder::LittleEndian>().map_err(Error::from_index)? as u64;
+            entry_offsets.push(entry_offset);
+            entry_offset += 12 + 4 * editor_count;
+        }
+        Ok(entry_offsets)
+    }
+
+    // Read the name of a user from the users section, given its number
+    fn read_user(&self, cursor: &mut Cursor<&[u8]>, user_number: u32) -> Result<String, Error> {
+        let section = self.section(SF_SECTION_USERS)?;
+        if user_number as usize >= self.fst.len() {
+            return Err(Error::Corrupt(format!("user {} doesn't exist, there are only {} users", user_number, self.fst.len())));
+        }
+        cursor.seek(SeekFrom::Start(section.offset + 8 * user_number as u64)).map_err(Error::from_index)?;
+        let user_offset = cursor.read_u64::<byteorder::LittleEndian>().map_err(Error::from_index)?;
+        if user_offset < section.offset || user_offset >= section.offset + section.length {
+            return Err(Error::Corrupt(format!("name of user {} at offset {} is outside of its section", user_number, user_offset)));
+        }
+        cursor.seek(SeekFrom::Start(user_offset)).map_err(Error::from_index)?;
+        let mut user = vec![];
+        cursor.read_until(0xA, &mut user).map_err(Error::from_index)?;
+        user.pop();
+        String::from_utf8(user).map_err(|_| Error::Corrupt(format!("name of user {} isn't valid UTF-8", user_number)))
+    }
+
+    fn section(&self, id: usize) -> Result<Section, Error> {
+        self.header.section(id).ok_or_else(|| Error::Corrupt(format!("section {} is missing", id)))
+    }
+}
+
+// Names of users given by hand, with underscores or surrounding spaces
+fn normalized_names(names: &[String]) -> HashSet<String> {
+    names.iter().map(|name| name.replace("_", " ").trim().to_string()).collect()
+}
+
+// Remove duplicates while keeping the order of the users (or pages), which is
+// the order of the editors (or pages) in the results
+fn unique_names(names: &[String]) -> Vec<String> {
+    let mut unique_names = Vec::with_capacity(names.len());
+    for name in names {
+        let name = name.replace("_", " ").trim().to_string();
+        if !unique_names.contains(&name) {
+            unique_names.push(name);
+        }
+    }
+    unique_names
+}
+
+struct InvertedList {
+    user: usize,
+    position: usize,
+    page_offsets: Vec<u64>,
+}
+
+/// Page modified by several of the users searched for
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct PageMatch {
+    /// Title of the page, including its namespace
+    pub title: String,
+    /// Namespace of the page (always 0 for indexes without namespaces)
+    pub namespace: i32,
+    /// Users who have modified the page, in the order they were searched for
+    pub editors: Vec<String>,
+    pub editor_count: usize,
+    /// Number of distinct users who have modified the page, searched for or
+    /// not, unless the index is in a format version which doesn't know it
+    pub total_editor_count: Option<usize>,
+    /// How much the page tells about the users who have modified it: the
+    /// number of them, weighted by the inverse frequency of the editors of the
+    /// page among all the users (0 if the total editor count is unknown)
+    #[serde(skip)]
+    pub rarity: f64,
+}
+
+// Page found by PageMatches, before its editors are resolved (the title and
+// the namespace are only read if needed)
+struct RawMatch {
+    title: String,
+    namespace: i32,
+    total_editor_count: Option<u32>,
+    // positions in users
+    editors: Vec<usize>,
+}
+
+/// Iterator on the pages modified by several users, returned by
+/// [`IndexReader::pages`] and [`IndexReader::pages_matching`]. Iteration stops after the first error.
+pub struct PageMatches<'a> {
+    reader: &'a IndexReader,
+    cursor: Cursor<&'a [u8]>,
+    users: Vec<String>,
+    lists: Vec<InvertedList>,
+    heap: BinaryHeap<Reverse<u64>>,
+    list_count: usize,
+    threshold: usize,
+    page_filter: Option<PageFilter>,
+    expression: Option<Expression>,
+    read_titles: bool,
+    unknown_users: Vec<String>,
+}
+
+impl PageMatches<'_> {
+    /// Users searched for who do not exist in the index or have no edits
+    pub fn unknown_users(&self) -> &[String] {
+        &self.unknown_users
+    }
+
+    // Find the next page
+    fn next_match(&mut self) -> Result<Option<RawMatch>, Error> {
+        while self.list_count >= self.threshold {
+            let Reverse(current_page_offset) = match self.heap.pop() {
+                Some(page_offset) => page_offset,
+                None => break,
+            };
+            // several lists can have pushed the same page, it must only be counted once
+            while self.heap.peek() == Some(&Reverse(current_page_offset)) {
+                self.heap.pop();
+            }
+            let mut editor_indexes = Vec::with_capacity(self.lists.len());
+            for list in &mut self.lists {
+                if list.position < list.page_offsets.len() &&
+                   list.page_offsets[list.position] == current_page_offset {
+                    editor_indexes.push(list.user);
+                    list.position += 1;
+                    if list.position < list.page_offsets.len() {
+                        self.heap.push(Reverse(list.page_offsets[list.position]));
+                    } else {
+                        self.list_count -= 1;
+                    }
+                }
+            }
+            if editor_indexes.len() < self.threshold {
+                continue;
+            }
+            if self.expression.as_ref().is_some_and(|expression| !expression.evaluate(&|user| editor_indexes.iter().any(|editor| self.users[*editor] == user))) {
+                continue;
+            }
+            let mut title = String::new();
+            let mut namespace = 0;
+            let mut total_editor_count = None;
+            if self.read_titles || self.page_filter.is_some() {
+                (namespace, total_editor_count) = read_page(&mut self.cursor, &self.reader.header, current_page_offset, &mut title)?;
+                if self.page_filter.as_ref().is_some_and(|page_filter| !page_filter.accepts(namespace, total_editor_count)) {
+                    continue;
+                }
+            }
+            return Ok(Some(RawMatch {
+                title,
+                namespace,
+                total_editor_count,
+                editors: editor_indexes,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for PageMatches<'_> {
+    type Item = Result<PageMatch, Error>;
+
+    fn next(&mut self) -> Option<Result<PageMatch, Error>> {
+        match self.next_match() {
+            Ok(page_match) => {
+                let RawMatch {
+                    title,
+                    namespace,
+                    total_editor_count,
+                    editors,
+                } = page_match?;
+                let rarity = match total_editor_count {
+                    Some(total_editor_count) if total_editor_count > 0 => editors.len() as f64 * (self.reader.user_count() as f64 / total_editor_count as f64).ln(),
+                    _ => 0.,
+                };
+                Some(Ok(PageMatch {
+                    title,
+                    namespace,
+                    editor_count: editors.len(),
+                    editors: editors.iter().map(|editor| self.users[*editor].clone()).collect(),
+                    total_editor_count: total_editor_count.map(|total_editor_count| total_editor_count as usize),
+                    rarity,
+                }))
+            },
+            Err(error) => {
+                self.list_count = 0;
+                Some(Err(error))
+            },
+        }
+    }
+}
+
+/// Sort pages found by [`IndexReader::pages`], which are in the order of the index otherwise
+pub fn sort_pages(pages: &mut [PageMatch], order: Order) {
+    match order {
+        Order::none => (),
+        Order::alphabetical => pages.sort_by(|first_page, second_page| first_page.title.cmp(&second_page.title)),
+        Order::count_decreasing => pages.sort_by_key(|page| Reverse(page.editor_count)),
+        Order::count_increasing => pages.sort_by_key(|page| page.editor_count),
+        Order::rarity => pages.sort_by(|first_page, second_page| second_page.rarity.total_cmp(&first_page.rarity)),
+    }
+}
+
+/// User who has modified several of the pages searched for
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct EditorMatch {
+    pub user: String,
+    /// Pages modified by the user, in the order they were searched for
+    pub pages: Vec<String>,
+    pub page_count: usize,
+}
+
+/// Users who have modified several pages, returned by [`IndexReader::editors`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct EditorMatches {
+    editors: Vec<EditorMatch>,
+    unknown_pages: Vec<String>,
+}
+
+impl EditorMatches {
+    /// Users found, in alphabetical order unless sorted
+    pub fn editors(&self) -> &[EditorMatch] {
+        &self.editors
+    }
+
+    /// Pages searched for which do not exist in the index
+    pub fn unknown_pages(&self) -> &[String] {
+        &self.unknown_pages
+    }
+
+    /// Reorder the users (the rarity order, which is about pages, is the
+    /// same as count_decreasing)
+    pub fn sort(&mut self, order: Order) {
+        match order {
+            Order::none => (),
+            Order::alphabetical => self.editors.sort_by(|first_editor, second_editor| first_editor.user.cmp(&second_editor.user)),
+            Order::count_decreasing |
+            Order::rarity => self.editors.sort_by_key(|editor| Reverse(editor.page_count)),
+            Order::count_increasing => self.editors.sort_by_key(|editor| editor.page_count),
+        }
+    }
+}
+
+/// User who has modified some of the pages modified by another user,
+/// returned by [`IndexReader::similar_users`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct SimilarUser {
+    pub user: String,
+    /// Number of pages modified by both users
+    pub count: u64,
+    /// Number of pages modified by the user
+    pub page_count: u64,
+    /// Similarity of the users according to the metric, which is minus the
+    /// decimal logarithm of the p-value for the significance
+    pub similarity: f64,
+    /// Jaccard index of the users estimated from their signatures, if they
+    /// have been found this way
+    pub estimated_jaccard: Option<f64>,
+}
+
+/// User who has modified pages of the seeds of an expansion
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct ExpandedUser {
+    pub user: String,
+    /// Hop at which the user has been found, from 1
+    pub hop: usize,
+    /// Number of seeds whose pages the user has modified, including those
+    /// found at the previous hops
+    pub seed_count: usize,
+    /// Number of pages of the seeds the user has modified
+    pub page_count: usize,
+    /// Number of seeds, each counting for its weight (1 for the seeds given,
+    /// decay for those found at the first hop, and so on)
+    pub seed_score: f64,
+    /// Number of pages, each counting for the highest weight of its seeds
+    pub page_score: f64,
+}
+
+/// Users who have modified pages of several seeds, returned by
+/// [`IndexReader::expand`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Expansion {
+    users: Vec<ExpandedUser>,
+    unknown_users: Vec<String>,
+}
+
+impl Expansion {
+    /// Users found, by hop then by decreasing scores
+    pub fn users(&self) -> &[ExpandedUser] {
+        &self.users
+    }
+
+    /// Seeds which do not exist in the index or have no edits
+    pub fn unknown_users(&self) -> &[String] {
+        &self.unknown_users
+    }
+}
+
+/// User whose signature is close to the one of another user, returned by
+/// [`IndexReader::similar_candidates`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct SimilarCandidate {
+    pub user: String,
+    pub estimated_jaccard: f64,
+}
+
+/// Page on which revisions of a user have directly followed revisions of
+/// another user, returned by [`IndexReader::follows`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct FollowedPage {
+    /// Title of the page, including its namespace
+    pub title: String,
+    pub namespace: i32,
+    /// Number of revisions of the user directly following a revision of the
+    /// other user
+    pub count: u32,
+    /// Shortest time between the revisions of the other user and the user
+    /// (in seconds)
+    pub shortest_delay: u32,
+    /// Average time between the revisions of the other user and the user (in
+    /// seconds, rounded down)
+    pub average_delay: u64,
+}
+
+/// Pages on which revisions of a user have directly followed revisions of
+/// another user, returned by [`IndexReader::follows`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Follows {
+    pages: Vec<FollowedPage>,
+    unknown_users: Vec<String>,
+}
+
+impl Follows {
+    /// Pages found, in the order of the index unless sorted
+    pub fn pages(&self) -> &[FollowedPage] {
+        &self.pages
+    }
+
+    /// Users searched for which do not exist in the index or have no edits
+    pub fn unknown_users(&self) -> &[String] {
+        &self.unknown_users
+    }
+
+    /// Reorder the pages (the rarity order is the same as count_decreasing)
+    pub fn sort(&mut self, order: Order) {
+        match order {
+            Order::none => (),
+            Order::alphabetical => self.pages.sort_by(|first_page, second_page| first_page.title.cmp(&second_page.title)),
+            Order::count_decreasing |
+            Order::rarity => self.pages.sort_by_key(|page| Reverse(page.count)),
+            Order::count_increasing => self.pages.sort_by_key(|page| page.count),
+        }
+    }
+}
+
+/// Users who have modified more pages together than with the other users,
+/// found by [`CooccurrenceMatrix::communities`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct Community {
+    /// Users of the community, in the order of the matrix
+    pub users: Vec<String>,
+    /// Fraction of the pairs of users of the community who have modified
+    /// pages together (0 for a single user)
+    pub density: f64,
+}
+
+/// User who has modified pages together with users of other communities
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct Bridge {
+    pub user: String,
+    /// Community of the user, from 1
+    pub community: usize,
+    /// Other communities with users of which the user has modified pages
+    pub linked_communities: Vec<usize>,
+    /// Participation coefficient of the user: 1 minus the sum of the squared
+    /// fractions of its similarity with the users of each community, which
+    /// is 0 if all of it is with a single community
+    pub participation: f64,
+}
+
+/// Communities of users, returned by [`CooccurrenceMatrix::communities`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Communities {
+    communities: Vec<Community>,
+    bridges: Vec<Bridge>,
+    modularity: f64,
+    unknown_users: Vec<String>,
+}
+
+impl Communities {
+    /// Communities, the largest first
+    pub fn communities(&self) -> &[Community] {
+        &self.communities
+    }
+
+    /// Users who bridge communities, by decreasing participation
+    pub fn bridges(&self) -> &[Bridge] {
+        &self.bridges
+    }
+
+    /// Modularity of the communities, from -0.5 (users have modified pages
+    /// only with users of other communities) to 1 (only within theirs)
+    pub fn modularity(&self) -> f64 {
+        self.modularity
+    }
+
+    /// Users searched for who do not exist in the index or have no edits
+    pub fn unknown_users(&self) -> &[String] {
+        &self.unknown_users
+    }
+}
+
+/// Number of pages modified by each pair of users, returned by
+/// [`IndexReader::cooccurrences`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CooccurrenceMatrix {
+    users: Vec<String>,
+    counts: Vec<u64>,
+    page_counts: Vec<u64>,
+    edit_counts: Vec<Option<u64>>,
+    total_page_count: u64,
+    unknown_users: Vec<String>,
+}
+
+impl CooccurrenceMatrix {
+    /// Users of the matrix, in the order of its rows and columns
+    pub fn users(&self) -> &[String] {
+        &self.users
+    }
+
+    /// Number of pages modified by both the users at these positions in the
+    /// matrix (0 on the diagonal)
+    pub fn count(&self, first_user: usize, second_user: usize) -> u64 {
+        self.counts[first_user * self.users.len() + second_user]
+    }
+
+    /// Number of pages modified by the user at this position in the matrix
+    /// and each of the other users
+    pub fn total(&self, user: usize) -> u64 {
+        (0..self.users.len()).map(|other_user| self.count(user, other_user)).sum()
+    }
+
+    /// Number of pages modified by the user at this position in the matrix
+    pub fn page_count(&self, user: usize) -> u64 {
+        self.page_counts[user]
+    }
+
+    /// Number of revisions of the user at this position in the matrix, in
+    /// the whole index whatever the filter, unless the index has no edit
+    /// counts
+    pub fn edit_count(&self, user: usize) -> Option<u64> {
+        self.edit_counts[user]
+    }
+
+    /// Similarity of the users at these positions in the matrix, according
+    /// to the metric (0 on the diagonal)
+    pub fn similarity(&self, first_user: usize, second_user: usize, metric: Metric) -> f64 {
+        similarity(self.count(first_user, second_user), self.page_count(first_user), self.page_count(second_user), self.total_page_count, metric)
+    }
+
+    /// Probability for the users at these positions in the matrix to have at
+    /// least as many pages in common if they had modified pages at random,
+    /// each the same number of pages as they did, among the pages of the
+    /// index (1 on the diagonal)
+    pub fn p_value(&self, first_user: usize, second_user: usize) -> f64 {
+        self.ln_p_value(first_user, second_user).exp()
+    }
+
+    // Logarithm of the p-value, which is still meaningful when the p-value
+    // is too small to be represented
+    fn ln_p_value(&self, first_user: usize, second_user: usize) -> f64 {
+        if first_user == second_user {
+            return 0.;
+        }
+        ln_hypergeometric_upper_tail(self.total_page_count, self.page_count(first_user), self.page_count(second_user), self.count(first_user, second_user))
+    }
+
+    /// Sum of the similarities of the user at this position in the matrix
+    /// with each of the other users
+    pub fn total_similarity(&self, user: usize, metric: Metric) -> f64 {
+        (0..self.users.len()).map(|other_user| self.similarity(user, other_user, metric)).sum()
+    }
+
+    /// Users searched for who do not exist in the index or have no edits
+    pub fn unknown_users(&self) -> &[String] {
+        &self.unknown_users
+    }
+
+    /// Group the users into communities which have modified more pages
+    /// together than with the other users, using the Louvain method on the
+    /// graph of their similarities according to the metric, and find the
+    /// users who bridge communities
+    pub fn communities(&self, metric: Metric) -> Communities {
+        // unknown users would otherwise each be a community on their own
+        let known_users: Vec<usize> = (0..self.users.len())
+            .filter(|user| !self.unknown_users.contains(&self.users[*user]))
+            .collect();
+        let mut matrix = self.clone();
+        matrix.permute(&known_users);
+        let user_count = matrix.users.len();
+        let weights: Vec<f64> = (0..user_count * user_count)
+            .map(|position| {
+                let similarity = matrix.similarity(position / user_count, position % user_count, metric);
+                // also excludes NaN
+                if similarity > 0. { similarity } else { 0. }
+            })
+            .collect();
+        let user_communities = community::louvain(&weights, user_count);
+        let modularity = community::modularity(&weights, user_count, &user_communities);
+        let community_count = user_communities.iter().max().map_or(0, |community| community + 1);
+        let mut sizes = vec![0; community_count];
+        for community in &user_communities {
+            sizes[*community] += 1;
+        }
+        // stable, so that communities of the same size stay in the order of
+        // their first user
+        let mut order: Vec<usize> = (0..community_count).collect();
+        order.sort_by_key(|community| Reverse(sizes[*community]));
+        let mut numbers = vec![0; community_count];
+        for (number, community) in order.iter().enumerate() {
+            numbers[*community] = number;
+        }
+        let user_communities: Vec<usize> = user_communities.iter().map(|community| numbers[*community]).collect();
+        let communities = (0..community_count)
+            .map(|community| {
+                let users: Vec<usize> = (0..user_count).filter(|user| user_communities[*user] == community).collect();
+                let pair_count = users.len() * users.len().saturating_sub(1) / 2;
+                let linked_pair_count = users.iter()
+                    .enumerate()
+                    .flat_map(|(position, first_user)| users[position + 1..].iter().map(move |second_user| (*first_user, *second_user)))
+                    .filter(|(first_user, second_user)| matrix.count(*first_user, *second_user) > 0)
+                    .count();
+                Community {
+                    users: users.iter().map(|user| matrix.users[*user].clone()).collect(),
+                    density: if pair_count > 0 { linked_pair_count as f64 / pair_count as f64 } else { 0. },
+                }
+            })
+            .collect();
+        let mut bridges = vec![];
+        for user in 0..user_count {
+            let mut community_weights = vec![0.; community_count];
+            for other_user in 0..user_count {
+                community_weights[user_communities[other_user]] += weights[user * user_count + other_user];
+            }
+            let linked_communities: Vec<usize> = (0..community_count)
+                .filter(|community| *community != user_communities[user] && community_weights[*community] > 0.)
+                .map(|community| community + 1)
+                .collect();
+            if !linked_communities.is_empty() {
+                let total_weight: f64 = community_weights.iter().sum();
+                bridges.push(Bridge {
+                    user: matrix.users[user].clone(),
+                    community: user_communities[user] + 1,
+                    linked_communities,
+                    participation: 1. - community_weights.iter().map(|weight| (weight / total_weight).powi(2)).sum::<f64>(),
+                });
+            }
+        }
+        bridges.sort_by(|first_bridge, second_bridge| second_bridge.participation.total_cmp(&first_bridge.participation));
+        Communities {
+            communities,
+            bridges,
+            modularity,
+            unknown_users: self.unknown_users.clone(),
+        }
+    }
+
+    /// Reorder the rows and columns of the matrix, by the sum of the
+    /// similarities of each user according to the metric for count orders
+    /// (the rarity order, which is about pages, is the same as
+    /// count_decreasing)
+    pub fn sort(&mut self, order: Order, metric: Metric) {
+        let mut permutation: Vec<usize> = (0..self.users.len()).collect();
+        let totals: Vec<f64> = (0..self.users.len()).map(|user| self.total_similarity(user, metric)).collect();
+        match order {
+            Order::none => return,
+            Order::alphabetical => permutation.sort_by(|first_user, second_user| self.users[*first_user].cmp(&self.users[*second_user])),
+            Order::count_decreasing | Order::rarity => permutation.sort_by(|first_user, second_user| totals[*second_user].total_cmp(&totals[*first_user])),
+            Order::count_increasing => permutation.sort_by(|first_user, second_user| totals[*first_user].total_cmp(&totals[*second_user])),
+        }
+        self.permute(&permutation);
+    }
+
+    /// Remove the users which have no pages in common with any of the other
+    /// users with a p-value of at most max_p_value
+    pub fn retain_significant(&mut self, max_p_value: f64) {
+        let user_count = self.users.len();
+        let retained: Vec<usize> = (0..user_count)
+            .filter(|user| (0..user_count).any(|other_user| other_user != *user && self.p_value(*user, other_user) <= max_p_value))
+            .collect();
+        self.permute(&retained);
+    }
+
+    // Keep only the rows and columns of these users, in this order
+    fn permute(&mut self, permutation: &[usize]) {
+        let user_count = permutation.len();
+        let mut counts = vec![0; user_count * user_count];
+        for (first_position, first_user) in permutation.iter().enumerate() {
+            for (second_position, second_user) in permutation.iter().enumerate() {
+                counts[first_position * user_count + second_position] = self.count(*first_user, *second_user);
+            }
+        }
+        self.users = permutation.iter().map(|user| self.users[*user].clone()).collect();
+        self.page_counts = permutation.iter().map(|user| self.page_counts[*user]).collect();
+        self.edit_counts = permutation.iter().map(|user| self.edit_counts[*user]).collect();
+        self.counts = counts;
+    }
+
+}
+
+/// Page modified by a user, returned by [`IndexReader::user_pages`]
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct UserPage {
+    /// Title of the page, including its namespace
+    pub title: String,
+    /// Namespace of the page (always 0 for indexes without namespaces)
+    pub namespace: i32,
+    /// Time of the first edit of the user on the page (in seconds since the
+    /// Unix epoch), unless the index has no timestamps
+    pub first_edit: Option<u32>,
+    /// Time of the last edit of the user on the page
+    pub last_edit: Option<u32>,
+}
+
+// Similarity of two users according to the metric, from the number of pages
+// they have both modified, the number of pages each of them has modified and
+// the number of pages they could have modified
+fn similarity(count: u64, first_page_count: u64, second_page_count: u64, total_page_count: u64, metric: Metric) -> f64 {
+    let denominator = match metric {
+        Metric::count => return count as f64,
+        Metric::significance => return -ln_hypergeometric_upper_tail(total_page_count, first_page_count, second_page_count, count) / std::f64::consts::LN_10,
+        Metric::jaccard => (first_page_count + second_page_count - count) as f64,
+        Metric::overlap => first_page_count.min(second_page_count) as f64,
+        Metric::cosine => (first_page_count as f64 * second_page_count as f64).sqrt(),
+        Metric::dice => (first_page_count + second_page_count) as f64 / 2.,
+    };
+    if count == 0 { 0. } else { count as f64 / denominator }
+}
+
+// Logarithm of the gamma function, using the Lanczos approximation (g = 7,
+// n = 9), which is precise enough for factorials of page counts
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2. * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+fn ln_binomial(n: u64, k: u64) -> f64 {
+    ln_gamma(n as f64 + 1.) - ln_gamma(k as f64 + 1.) - ln_gamma((n - k) as f64 + 1.)
+}
+
+// Logarithm of the probability to draw at least successes successes in draws
+// draws without replacement from a population of size population with
+// success_count successes, summed in log space as the terms can be very small
+fn ln_hypergeometric_upper_tail(population: u64, success_count: u64, draws: u64, successes: u64) -> f64 {
+    let max_successes = success_count.min(draws);
+    if successes == 0 {
+        return 0.;
+    }
+    if successes > max_successes {
+        return f64::NEG_INFINITY;
+    }
+    // the counts of the users can't be consistent with a smaller population
+    let population = population.max(success_count + draws - successes);
+    let mut ln_term = ln_binomial(success_count, successes) +
+        ln_binomial(population - success_count, draws - successes) -
+        ln_binomial(population, draws);
+    let mut ln_sum = ln_term;
+    let mode = (draws + 1) as f64 * (success_count + 1) as f64 / (population + 2) as f64;
+    for x in successes..max_successes {
+        ln_term += ((success_count - x) as f64).ln() + ((draws - x) as f64).ln() -
+            ((x + 1) as f64).ln() - ((population + x + 1 - success_count - draws) as f64).ln();
+        ln_sum = ln_sum.max(ln_term) + (1. + (-(ln_sum - ln_term).abs()).exp()).ln();
+        // the terms only decrease past the mode
+        if x as f64 > mode && ln_term < ln_sum - 40. {
+            break;
+        }
+    }
+    ln_sum.min(0.)
+}
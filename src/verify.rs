@@ -0,0 +1,497 @@
+use byteorder::ReadBytesExt;
+
+use fst::Streamer;
+
+use std::io::{
+    BufRead,
+    Cursor,
+    Read,
+    SeekFrom,
+    Write,
+};
+
+use crate::{
+    adjacency::Adjacency,
+    read_edit_counts,
+    read_index_header,
+    read_metadata,
+    signatures::SignatureBuilder,
+    Error,
+    Index,
+    IndexHeader,
+    OffsetWriter,
+    Section,
+    SF_HEADER_LENGTH,
+    SF_SECTION_ADJACENCY,
+    SF_SECTION_EDIT_COUNTS,
+    SF_SECTION_NAMESPACES,
+    SF_SECTION_PAGE_EDITORS,
+    SF_SECTION_SIGNATURES,
+    SF_SECTION_TITLES,
+    SF_SECTION_USERS,
+    SF_VERSION_32_BITS,
+    SF_VERSION_WITHOUT_EDITOR_COUNTS,
+    SF_VERSION_WITHOUT_NAMESPACES,
+    SF_VERSION_WITHOUT_PAGE_EDITORS,
+    SF_VERSION_WITHOUT_TIMESTAMPS,
+};
+
+// Past this number, problems are counted but not reported anymore
+const SF_MAX_REPORTED_PROBLEMS: usize = 100;
+
+struct Report<'a> {
+    writer: &'a mut dyn Write,
+    problem_count: usize,
+}
+
+impl Report<'_> {
+    fn problem(&mut self, problem: String) {
+        self.problem_count += 1;
+        let result = if self.problem_count <= SF_MAX_REPORTED_PROBLEMS {
+            writeln!(self.writer, "Error: {}", problem)
+        } else if self.problem_count == SF_MAX_REPORTED_PROBLEMS + 1 {
+            writeln!(self.writer, "Error: too many problems, only the first {} are reported", SF_MAX_REPORTED_PROBLEMS)
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(()) => (),
+            Err(_) => (), // ignore output error
+        }
+    }
+}
+
+// Read the page records, which go from the header to the postings, and return
+// the offset at which each of them starts
+fn verify_page_names(index: &mut dyn Index, header: &IndexHeader, postings_offset: u64, report: &mut Report) -> std::io::Result<Vec<u64>> {
+    let mut title_offsets = vec![];
+    index.seek(SeekFrom::Start(SF_HEADER_LENGTH))?;
+    let mut page_reader = index.take(postings_offset - SF_HEADER_LENGTH);
+    let mut page_offset = SF_HEADER_LENGTH;
+    let mut title = vec![];
+    loop {
+        let mut record_length = 0;
+        if header.version > SF_VERSION_WITHOUT_NAMESPACES {
+            match page_reader.read_i32::<byteorder::LittleEndian>() {
+                Ok(_) => record_length += 4,
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    if page_offset < postings_offset {
+                        report.problem(format!("page record at offset {} is truncated by the postings at offset {}", page_offset, postings_offset));
+                    }
+                    break;
+                },
+                Err(error) => return Err(error),
+            }
+        }
+        if header.version > SF_VERSION_WITHOUT_EDITOR_COUNTS {
+            match page_reader.read_u32::<byteorder::LittleEndian>() {
+                Ok(_) => record_length += 4,
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    report.problem(format!("page record at offset {} is truncated by the postings at offset {}", page_offset, postings_offset));
+                    break;
+                },
+                Err(error) => return Err(error),
+            }
+        }
+        record_length += page_reader.read_until(0xA, &mut title)? as u64;
+        if record_length == 0 {
+            break;
+        }
+        if title.last() != Some(&0xA) {
+            report.problem(format!("page record at offset {} is truncated by the postings at offset {}", page_offset, postings_offset));
+            break;
+        }
+        if std::str::from_utf8(&title).is_err() {
+            report.problem(format!("page name at offset {} isn't valid UTF-8", page_offset));
+        }
+        title_offsets.push(page_offset);
+        page_offset += record_length;
+        title.clear();
+    }
+    Ok(title_offsets)
+}
+
+// Postings of a user: where they start, how many they are and how long each of them is
+fn postings_location(index: &mut dyn Index, header: &IndexHeader, value: u64) -> std::io::Result<(u64, u64, u64)> {
+    if header.version == SF_VERSION_32_BITS {
+        Ok((value >> 32, value & 0xFF_FF_FF_FF, 4))
+    } else {
+        index.seek(SeekFrom::Start(value))?;
+        let posting_length = if header.version == SF_VERSION_WITHOUT_TIMESTAMPS { 8 } else { 16 };
+        Ok((value + 8, index.read_u64::<byteorder::LittleEndian>()?, posting_length))
+    }
+}
+
+// Check the page records and the postings, and return the offsets of the
+// page records
+fn verify_postings(index: &mut dyn Index, header: &IndexHeader, fst: &fst::Map<Vec<u8>>, report: &mut Report) -> std::io::Result<Vec<u64>> {
+    let fst_offset = header.fst_section().offset;
+    let postings_offset = match fst.stream().next() {
+        Some((_, value)) => if header.version == SF_VERSION_32_BITS { value >> 32 } else { value },
+        None => fst_offset,
+    };
+    if postings_offset < SF_HEADER_LENGTH || postings_offset > fst_offset {
+        report.problem(format!("postings of the first user are at offset {}, outside of the index (between {} and {})", postings_offset, SF_HEADER_LENGTH, fst_offset));
+        return Ok(vec![]);
+    }
+    let title_offsets = verify_page_names(index, header, postings_offset, report)?;
+    // postings are written user after user, in the order of the FST
+    let mut expected_offset = postings_offset;
+    let mut previous_user = String::new();
+    let mut stream = fst.stream();
+    while let Some((user, value)) = stream.next() {
+        let user = String::from_utf8_lossy(user).to_string();
+        let value_offset = if header.version == SF_VERSION_32_BITS { value >> 32 } else { value };
+        if value_offset != expected_offset {
+            if previous_user.is_empty() {
+                report.problem(format!("postings of user {} are at offset {}, expected {}", user, value_offset, expected_offset));
+            } else {
+                report.problem(format!("postings of user {} are at offset {}, expected {} (right after the postings of user {})", user, value_offset, expected_offset, previous_user));
+            }
+            if value_offset < postings_offset || value_offset >= fst_offset {
+                previous_user = user;
+                continue;
+            }
+        }
+        let (offset, posting_count, posting_length) = postings_location(index, header, value)?;
+        let end_offset = posting_count.checked_mul(posting_length).and_then(|length| length.checked_add(offset));
+        if end_offset.is_none_or(|end_offset| end_offset > fst_offset) {
+            report.problem(format!("postings of user {} ({} postings at offset {}) overlap the FST at offset {}", user, posting_count, offset, fst_offset));
+            previous_user = user;
+            continue;
+        }
+        if posting_count == 0 {
+            report.problem(format!("user {} has no postings", user));
+        }
+        index.seek(SeekFrom::Start(offset))?;
+        let mut previous_page_offset = None;
+        for posting in 0..posting_count {
+            let page_offset = if header.version == SF_VERSION_32_BITS {
+                index.read_u32::<byteorder::LittleEndian>()? as u64
+            } else {
+                index.read_u64::<byteorder::LittleEndian>()?
+            };
+            if title_offsets.binary_search(&page_offset).is_err() {
+                report.problem(format!("posting {} of user {} points at offset {}, which isn't the start of a page name", posting, user, page_offset));
+            }
+            if previous_page_offset.is_some_and(|previous_page_offset| page_offset <= previous_page_offset) {
+                report.problem(format!("postings of user {} aren't sorted: posting {} points at offset {}, after offset {}", user, posting, page_offset, previous_page_offset.unwrap()));
+            }
+            previous_page_offset = Some(page_offset);
+            if header.version > SF_VERSION_WITHOUT_TIMESTAMPS {
+                let first_edit = index.read_u32::<byteorder::LittleEndian>()?;
+                let last_edit = index.read_u32::<byteorder::LittleEndian>()?;
+                if first_edit > last_edit {
+                    report.problem(format!("posting {} of user {} has its first edit ({}) after its last edit ({})", posting, user, first_edit, last_edit));
+                }
+            }
+        }
+        expected_offset = end_offset.unwrap();
+        previous_user = user;
+    }
+    if expected_offset != fst_offset {
+        report.problem(format!("postings end at offset {}, but the FST starts at offset {}", expected_offset, fst_offset));
+    }
+    Ok(title_offsets)
+}
+
+// Check the users, page editors and titles sections against the FST of the
+// users and the page records
+fn verify_page_editors(index: &mut dyn Index, header: &IndexHeader, fst: &fst::Map<Vec<u8>>, title_offsets: &[u64], signature_builder: &mut Option<SignatureBuilder>, report: &mut Report) -> std::io::Result<()> {
+    let (users_section, page_editors_section, titles_section) = match (header.section(SF_SECTION_USERS), header.section(SF_SECTION_PAGE_EDITORS), header.section(SF_SECTION_TITLES)) {
+        (Some(users_section), Some(page_editors_section), Some(titles_section)) => (users_section, page_editors_section, titles_section),
+        _ => {
+            report.problem("users, page editors or titles section is missing".to_string());
+            return Ok(());
+        },
+    };
+    // names follow their offsets, in the order of the FST
+    let user_count = fst.len() as u64;
+    if users_section.length < 8 * user_count {
+        report.problem(format!("users section at offset {} is too short for the offsets of {} users", users_section.offset, user_count));
+    } else {
+        let mut expected_offset = users_section.offset + 8 * user_count;
+        let mut users = fst.keys();
+        let mut user_number = 0;
+        let mut name = vec![];
+        while let Some(user) = users.next() {
+            index.seek(SeekFrom::Start(users_section.offset + 8 * user_number))?;
+            let user_offset = index.read_u64::<byteorder::LittleEndian>()?;
+            if user_offset != expected_offset {
+                report.problem(format!("name of user {} is at offset {}, expected {}", String::from_utf8_lossy(user), user_offset, expected_offset));
+            } else {
+                index.seek(SeekFrom::Start(user_offset))?;
+                index.take(users_section.offset + users_section.length - user_offset).read_until(0xA, &mut name)?;
+                if name.last() != Some(&0xA) || name[..name.len() - 1] != *user {
+                    report.problem(format!("name of user {} at offset {} is {}", String::from_utf8_lossy(user), user_offset, String::from_utf8_lossy(&name)));
+                }
+                name.clear();
+            }
+            expected_offset += user.len() as u64 + 1;
+            user_number += 1;
+        }
+        if expected_offset != users_section.offset + users_section.length {
+            report.problem(format!("user names end at offset {}, but the users section ends at offset {}", expected_offset, users_section.offset + users_section.length));
+        }
+    }
+    // one entry per page, in the order of the page records
+    let mut entry_offsets = Vec::with_capacity(title_offsets.len());
+    let mut entry_offset = page_editors_section.offset;
+    let page_editors_end_offset = page_editors_section.offset + page_editors_section.length;
+    let mut editors = vec![];
+    index.seek(SeekFrom::Start(entry_offset))?;
+    for page_offset in title_offsets {
+        if entry_offset + 12 > page_editors_end_offset {
+            report.problem(format!("page editors section ends at offset {} before the entry of the page at offset {}", page_editors_end_offset, page_offset));
+            break;
+        }
+        let entry_page_offset = index.read_u64::<byteorder::LittleEndian>()?;
+        let editor_count = index.read_u32::<byteorder::LittleEndian>()? as u64;
+        if entry_page_offset != *page_offset {
+            report.problem(format!("page editors at offset {} are those of the page at offset {}, expected {}", entry_offset, entry_page_offset, page_offset));
+        }
+        if entry_offset + 12 + 4 * editor_count > page_editors_end_offset {
+            report.problem(format!("{} page editors at offset {} are truncated by the end of their section", editor_count, entry_offset));
+            break;
+        }
+        let mut previous_editor = None;
+        for _ in 0..editor_count {
+            let editor = index.read_u32::<byteorder::LittleEndian>()?;
+            if editor as u64 >= user_count {
+                report.problem(format!("page editors at offset {} include user {}, but there are only {} users", entry_offset, editor, user_count));
+            } else {
+                editors.push(editor);
+            }
+            if previous_editor.is_some_and(|previous_editor| editor <= previous_editor) {
+                report.problem(format!("page editors at offset {} aren't sorted: user {} is after user {}", entry_offset, editor, previous_editor.unwrap()));
+            }
+            previous_editor = Some(editor);
+        }
+        if let Some(signature_builder) = signature_builder {
+            signature_builder.add_page(entry_page_offset, &editors);
+        }
+        editors.clear();
+        entry_offsets.push(entry_offset);
+        entry_offset += 12 + 4 * editor_count;
+    }
+    if entry_offsets.len() == title_offsets.len() && entry_offset != page_editors_end_offset {
+        report.problem(format!("page editors end at offset {}, but their section ends at offset {}", entry_offset, page_editors_end_offset));
+    }
+    let mut titles_bytes = vec![];
+    index.seek(SeekFrom::Start(titles_section.offset))?;
+    index.take(titles_section.length).read_to_end(&mut titles_bytes)?;
+    match fst::Map::new(titles_bytes).and_then(|titles| titles.as_fst().verify().map(|_| titles)) {
+        Ok(titles) => {
+            let mut stream = titles.stream();
+            while let Some((title, entry_offset)) = stream.next() {
+                if entry_offsets.binary_search(&entry_offset).is_err() {
+                    report.problem(format!("title {} points at offset {}, which isn't the start of page editors", String::from_utf8_lossy(title), entry_offset));
+                }
+            }
+        },
+        Err(error) => report.problem(format!("titles FST at offset {} is corrupt: {}", titles_section.offset, error)),
+    }
+    Ok(())
+}
+
+// Compare the signatures section with the signatures computed again from the
+// page editors
+fn verify_signatures(index: &mut dyn Index, header: &IndexHeader, signature_builder: SignatureBuilder, report: &mut Report) -> std::io::Result<()> {
+    let section = match header.section(SF_SECTION_SIGNATURES) {
+        Some(section) => section,
+        None => return Ok(()),
+    };
+    let mut expected_bytes = vec![];
+    signature_builder.write(&mut OffsetWriter {
+        writer: &mut expected_bytes,
+        offset: section.offset,
+    })?;
+    let mut bytes = vec![];
+    index.seek(SeekFrom::Start(section.offset))?;
+    index.take(section.length).read_to_end(&mut bytes)?;
+    if bytes.len() != expected_bytes.len() {
+        report.problem(format!("signatures section at offset {} is {} bytes long, expected {}", section.offset, bytes.len(), expected_bytes.len()));
+    } else if let Some(position) = bytes.iter().zip(&expected_bytes).position(|(byte, expected_byte)| byte != expected_byte) {
+        report.problem(format!("signatures section doesn't match the page editors from offset {}", section.offset + position as u64));
+    }
+    Ok(())
+}
+
+// Check that each user has at least one revision per page it has modified,
+// and return the total number of revisions
+fn verify_edit_counts(index: &mut dyn Index, header: &IndexHeader, fst: &fst::Map<Vec<u8>>, report: &mut Report) -> std::io::Result<Option<u64>> {
+    let section = match header.section(SF_SECTION_EDIT_COUNTS) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let edit_counts = match read_edit_counts(index, section, fst.len()) {
+        Ok(edit_counts) => edit_counts,
+        Err(Error::Io(error)) => return Err(error),
+        Err(error) => {
+            report.problem(error.to_string());
+            return Ok(None);
+        },
+    };
+    let mut stream = fst.stream();
+    let mut user_number = 0;
+    while let Some((user, value)) = stream.next() {
+        let (_, posting_count, _) = postings_location(index, header, value)?;
+        if edit_counts[user_number] < posting_count {
+            report.problem(format!("user {} has {} revisions, but has modified {} pages", String::from_utf8_lossy(user), edit_counts[user_number], posting_count));
+        }
+        user_number += 1;
+    }
+    Ok(Some(edit_counts.iter().sum()))
+}
+
+// Check that the entries of each user are sorted and refer to other users and
+// to pages of the index
+fn verify_adjacency(index: &mut dyn Index, header: &IndexHeader, user_count: usize, title_offsets: &[u64], report: &mut Report) -> std::io::Result<()> {
+    let section = match header.section(SF_SECTION_ADJACENCY) {
+        Some(section) => section,
+        None => return Ok(()),
+    };
+    let mut bytes = vec![];
+    index.seek(SeekFrom::Start(section.offset))?;
+    index.take(section.length).read_to_end(&mut bytes)?;
+    let mut cursor = Cursor::new(bytes.as_slice());
+    let section = Section {
+        offset: 0,
+        length: bytes.len() as u64,
+    };
+    let result = Adjacency::read(section, user_count as u64).and_then(|adjacency| {
+        for follower in 0..user_count as u32 {
+            let mut previous_entry = None;
+            for follow in adjacency.follows(&mut cursor, follower)? {
+                if follow.followed as usize >= user_count || follow.followed == follower {
+                    report.problem(format!("user {} follows user {}, which isn't another user of the index", follower, follow.followed));
+                }
+                if title_offsets.binary_search(&follow.page_offset).is_err() {
+                    report.problem(format!("user {} follows user {} on page at offset {}, which isn't the start of a page", follower, follow.followed, follow.page_offset));
+                }
+                if follow.delays.count == 0 || (follow.delays.shortest as u64) * (follow.delays.count as u64) > follow.delays.total {
+                    report.problem(format!("user {} follows user {} {} times on page at offset {} after {} seconds at least, but after {} seconds in total", follower, follow.followed, follow.delays.count, follow.page_offset, follow.delays.shortest, follow.delays.total));
+                }
+                if previous_entry.is_some_and(|previous_entry| previous_entry >= (follow.followed, follow.page_offset)) {
+                    report.problem(format!("entries of user {} aren't sorted by followed user and page", follower));
+                }
+                previous_entry = Some((follow.followed, follow.page_offset));
+            }
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => Ok(()),
+        Err(Error::Io(error)) => Err(error),
+        Err(error) => {
+            report.problem(error.to_string());
+            Ok(())
+        },
+    }
+}
+
+fn verify_sections(index: &mut dyn Index, header: &IndexHeader, report: &mut Report) -> std::io::Result<()> {
+    // sections are written one after the other, from the FST to the footer
+    let mut sections: Vec<_> = header.sections.iter()
+        .enumerate()
+        .filter(|(_, section)| section.length > 0)
+        .collect();
+    sections.sort_unstable_by_key(|(_, section)| section.offset);
+    let mut expected_offset = header.fst_section().offset;
+    for (section_id, section) in sections {
+        if section.offset != expected_offset {
+            report.problem(format!("section {} is at offset {}, expected {}", section_id, section.offset, expected_offset));
+        }
+        expected_offset = section.offset + section.length;
+    }
+    if expected_offset != header.footer_offset {
+        report.problem(format!("sections end at offset {}, but the footer starts at offset {}", expected_offset, header.footer_offset));
+    }
+    if let Some(section) = header.section(SF_SECTION_NAMESPACES) {
+        index.seek(SeekFrom::Start(section.offset))?;
+        let mut section_reader = index.take(section.length);
+        let mut name = vec![];
+        let mut namespace_offset = section.offset;
+        while section_reader.read_i32::<byteorder::LittleEndian>().is_ok() {
+            let name_length = section_reader.read_until(0xA, &mut name)? as u64;
+            if name.last() != Some(&0xA) {
+                report.problem(format!("namespace at offset {} is truncated by the end of the namespaces section", namespace_offset));
+            }
+            namespace_offset += 4 + name_length;
+            name.clear();
+        }
+        if namespace_offset != section.offset + section.length {
+            report.problem(format!("namespace at offset {} is truncated by the end of the namespaces section", namespace_offset));
+        }
+    }
+    Ok(())
+}
+
+/// Check the whole index for corruption or truncation, writing every
+/// problem found (up to a limit) and a summary line to the writer. Fails if
+/// any problem has been found.
+pub fn verify(index: &mut dyn Index, writer: &mut dyn Write) -> Result<(), Error> {
+    let header = read_index_header(index)?;
+    let mut report = Report {
+        writer,
+        problem_count: 0,
+    };
+    let fst_section = header.fst_section();
+    let mut fst_bytes = vec![];
+    index.seek(SeekFrom::Start(fst_section.offset))?;
+    index.take(fst_section.length).read_to_end(&mut fst_bytes)?;
+    // the checksum has to be verified before walking the FST, which could panic otherwise
+    let fst = fst::Map::new(fst_bytes).and_then(|fst| fst.as_fst().verify().map(|_| fst));
+    let (page_count, user_count, revision_count) = match fst {
+        Ok(fst) => {
+            let title_offsets = verify_postings(index, &header, &fst, &mut report)?;
+            if header.version > SF_VERSION_WITHOUT_PAGE_EDITORS && !title_offsets.is_empty() {
+                let mut signature_builder = header.section(SF_SECTION_SIGNATURES).map(|_| SignatureBuilder::new(fst.len()));
+                verify_page_editors(index, &header, &fst, &title_offsets, &mut signature_builder, &mut report)?;
+                if let Some(signature_builder) = signature_builder {
+                    verify_signatures(index, &header, signature_builder, &mut report)?;
+                }
+            }
+            let revision_count = verify_edit_counts(index, &header, &fst, &mut report)?;
+            verify_adjacency(index, &header, fst.len(), &title_offsets, &mut report)?;
+            (title_offsets.len(), fst.len(), revision_count)
+        },
+        Err(error) => {
+            let error = match error {
+                fst::Error::Fst(error) => error.to_string(),
+                error => error.to_string(),
+            };
+            report.problem(format!("FST at offset {} is corrupt: {}", fst_section.offset, error));
+            (0, 0, None)
+        },
+    };
+    verify_sections(index, &header, &mut report)?;
+    match read_metadata(index, &header) {
+        Ok(Some(metadata)) => {
+            if metadata.page_count != page_count as u64 {
+                report.problem(format!("metadata give {} pages, but the index has {}", metadata.page_count, page_count));
+            }
+            if metadata.user_count != user_count as u64 {
+                report.problem(format!("metadata give {} users, but the index has {}", metadata.user_count, user_count));
+            }
+            if revision_count.is_some_and(|revision_count| metadata.revision_count != revision_count) {
+                report.problem(format!("metadata give {} revisions, but the users have {}", metadata.revision_count, revision_count.unwrap()));
+            }
+        },
+        Ok(None) => (),
+        Err(error) => report.problem(format!("metadata are unreadable: {}", error)),
+    }
+    if report.problem_count > 0 {
+        match writeln!(report.writer, "{} problem(s) found in index in format version {}", report.problem_count, header.version) {
+            Ok(()) => (),
+            Err(_) => (), // ignore output error
+        }
+        Err(Error::Corrupt(format!("{} problem(s) found", report.problem_count)))
+    } else {
+        match writeln!(report.writer, "No problem found in index in format version {} ({} pages, {} users)", header.version, page_count, user_count) {
+            Ok(()) => (),
+            Err(_) => (), // ignore output error
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,838 @@
+use std::{
+    io::{
+        Cursor,
+        Write,
+    },
+    ops::Range,
+};
+
+use byteorder::WriteBytesExt;
+
+const DUMP: &[u8] = include_bytes!("data/stub-meta-history.xml");
+
+// Builds at a fixed time, to get the same index byte for byte
+fn build_options() -> socksfinder::BuildOptions {
+    socksfinder::BuildOptions {
+        build_time: Some(1679270400),
+        ..Default::default()
+    }
+}
+
+// Builds an index of pages named "Page <n>", in the namespace given by their
+// number, each of them modified once by each user whose pages include it
+fn build_user_pages_index(page_count: usize, user_pages: &[(&str, Range<usize>)], namespace: impl Fn(usize) -> i32, options: &socksfinder::BuildOptions) -> Vec<u8> {
+    let mut dump = String::from("<mediawiki>");
+    for page in 0..page_count {
+        dump.push_str(&format!("<page><title>Page {}</title><ns>{}</ns>", page, namespace(page)));
+        for (user, pages) in user_pages {
+            if pages.contains(&page) {
+                dump.push_str(&format!("<revision><timestamp>2020-05-06T12:00:00Z</timestamp><contributor><username>{}</username></contributor></revision>", user));
+            }
+        }
+        dump.push_str("</page>");
+    }
+    dump.push_str("</mediawiki>");
+    let mut index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(dump.as_bytes())], &mut index, options).unwrap();
+    index
+}
+
+// Ids of the sections listed in the footer of an index
+const FST_SECTION: usize = 0;
+const METADATA_SECTION: usize = 2;
+const TITLES_SECTION: usize = 5;
+const SIGNATURES_SECTION: usize = 6;
+const EDIT_COUNTS_SECTION: usize = 7;
+const ADJACENCY_SECTION: usize = 8;
+
+// Offset and length of a section, from the footer which lists the offset and
+// length of each section, then their number
+fn section(index: &[u8], section_id: usize) -> (usize, usize) {
+    let read_u64 = |offset: usize| u64::from_le_bytes(index[offset..offset + 8].try_into().unwrap()) as usize;
+    let section_count = read_u64(index.len() - 8);
+    assert!(section_id < section_count);
+    let entry_offset = index.len() - 8 - 16 * section_count + 16 * section_id;
+    (read_u64(entry_offset), read_u64(entry_offset + 8))
+}
+
+fn build_index() -> Vec<u8> {
+    let mut index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(DUMP)], &mut index, &build_options()).unwrap();
+    index
+}
+
+fn query(index: &[u8], users: &[&str], threshold: usize, order: socksfinder::Order) -> String {
+    query_with_filter(index, users, threshold, order, &socksfinder::Filter::default())
+}
+
+fn query_with_filter(index: &[u8], users: &[&str], threshold: usize, order: socksfinder::Order, filter: &socksfinder::Filter) -> String {
+    let users: Vec<String> = users.iter().map(|user| user.to_string()).collect();
+    let mut output = vec![];
+    let index = socksfinder::IndexReader::from_bytes(index.to_vec()).unwrap();
+    socksfinder::query(&index, &mut output, &users, threshold, None, order, filter, false, socksfinder::Metric::count, None, 1, socksfinder::Format::text, false).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn build_and_query() {
+    let index = build_index();
+    assert_eq!(&index[0..4], b"SF\x05\x00");
+    assert_eq!(
+        query(&index, &["Arkanosis", "Arktest", "Arkbot"], 0, socksfinder::Order::none),
+        "Utilisateur:Arktest/test: 3/3 (Arkanosis, Arktest, Arkbot)\n\
+         Projet:Articles sans portail/1: 3/3 (Arkanosis, Arktest, Arkbot)\n",
+    );
+    assert_eq!(
+        query(&index, &["Arkanosis", "Arktest"], 2, socksfinder::Order::alphabetical),
+        "Modèle:Infobox Equipe MotoGP/Bac à sable: 2/2 (Arkanosis, Arktest)\n\
+         Projet:Articles sans portail/1: 2/3 (Arkanosis, Arktest)\n\
+         Utilisateur:Arktest/test: 2/3 (Arkanosis, Arktest)\n",
+    );
+}
+
+#[test]
+fn build_with_memory_budget() {
+    let index = build_index();
+    for memory_budget in [0, 100, 200, 1000] {
+        let mut external_index = vec![];
+        let options = socksfinder::BuildOptions {
+            memory_budget: Some(memory_budget),
+            ..build_options()
+        };
+        socksfinder::build(&mut [&mut Cursor::new(DUMP)], &mut external_index, &options).unwrap();
+        let (titles_offset, titles_length) = section(&index, TITLES_SECTION);
+        let (external_titles_offset, external_titles_length) = section(&external_index, TITLES_SECTION);
+        assert!(index[titles_offset..titles_offset + titles_length] == external_index[external_titles_offset..external_titles_offset + external_titles_length], "titles built with a memory budget of {} B differ", memory_budget);
+        assert!(index == external_index, "index built with a memory budget of {} B differs", memory_budget);
+    }
+}
+
+#[test]
+fn query_32_bits_index() {
+    // Format version 0: 32-bit offsets, edit count packed in the FST value
+    let mut index = vec![];
+    index.extend_from_slice(b"SF\x00\x00");
+    let first_page_offset = index.len() as u32;
+    index.extend_from_slice(b"Aholfing\n");
+    let second_page_offset = index.len() as u32;
+    index.extend_from_slice(b"Utilisateur:Arktest/test\n");
+    let arkbot_offset = index.len() as u64;
+    index.write_u32::<byteorder::LittleEndian>(first_page_offset).unwrap();
+    index.write_u32::<byteorder::LittleEndian>(second_page_offset).unwrap();
+    let arktest_offset = index.len() as u64;
+    index.write_u32::<byteorder::LittleEndian>(second_page_offset).unwrap();
+    let fst_offset = index.len() as u32;
+    let mut fst_builder = fst::MapBuilder::new(&mut index).unwrap();
+    fst_builder.insert("Arkbot", arkbot_offset << 32 | 2).unwrap();
+    fst_builder.insert("Arktest", arktest_offset << 32 | 1).unwrap();
+    fst_builder.finish().unwrap();
+    index.write_u32::<byteorder::LittleEndian>(fst_offset).unwrap();
+    assert_eq!(
+        query(&index, &["Arkbot", "Arktest"], 0, socksfinder::Order::none),
+        "Utilisateur:Arktest/test: 2 (Arkbot, Arktest)\n",
+    );
+    socksfinder::verify(&mut Cursor::new(&index), &mut vec![]).unwrap();
+}
+
+#[test]
+fn query_time_window() {
+    let index = build_index();
+    let filter = socksfinder::Filter {
+        since: socksfinder::parse_date("2016-01-01", false),
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        "Projet:Articles sans portail/1: 3/3 (Arkanosis, Arktest, Arkbot)\n\
+         Utilisateur:Arktest/test: 2/3 (Arktest, Arkbot)\n",
+    );
+    let filter = socksfinder::Filter {
+        since: socksfinder::parse_date("2011-02-01", false),
+        until: socksfinder::parse_date("2011-02-01", true),
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        "Utilisateur:Arktest/test: 2/3 (Arkanosis, Arktest)\n",
+    );
+    assert_eq!(socksfinder::parse_date("2001-01-21T02:12:21Z", false), Some(980043141));
+    assert_eq!(socksfinder::parse_date("2001-01-21", true), Some(980121599));
+    assert_eq!(socksfinder::parse_date("21/01/2001", false), None);
+}
+
+#[test]
+fn query_namespaces() {
+    let index = build_index();
+    let filter = socksfinder::Filter {
+        excluded_namespaces: vec!["Projet".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        "Aholfing: 2/3 (Arktest, Arkbot)\n\
+         Modèle:Infobox Equipe MotoGP/Bac à sable: 2/2 (Arkanosis, Arktest)\n\
+         Utilisateur:Arktest/test: 3/3 (Arkanosis, Arktest, Arkbot)\n",
+    );
+    let filter = socksfinder::Filter {
+        namespaces: vec!["0".to_string(), "utilisateur".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        "Aholfing: 2/3 (Arktest, Arkbot)\n\
+         Utilisateur:Arktest/test: 3/3 (Arkanosis, Arktest, Arkbot)\n",
+    );
+}
+
+#[test]
+fn query_rarity() {
+    // Modèle:Infobox Equipe MotoGP/Bac à sable has 2 editors, the other pages 3
+    let index = build_index();
+    assert_eq!(
+        query(&index, &["Arkanosis", "Arktest"], 2, socksfinder::Order::rarity),
+        "Modèle:Infobox Equipe MotoGP/Bac à sable: 2/2 (Arkanosis, Arktest)\n\
+         Utilisateur:Arktest/test: 2/3 (Arkanosis, Arktest)\n\
+         Projet:Articles sans portail/1: 2/3 (Arkanosis, Arktest)\n",
+    );
+    assert_eq!(
+        query(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::rarity),
+        "Modèle:Infobox Equipe MotoGP/Bac à sable: 2/2 (Arkanosis, Arktest)\n\
+         Utilisateur:Arktest/test: 3/3 (Arkanosis, Arktest, Arkbot)\n\
+         Projet:Articles sans portail/1: 3/3 (Arkanosis, Arktest, Arkbot)\n\
+         Aholfing: 2/3 (Arktest, Arkbot)\n",
+    );
+}
+
+#[test]
+fn query_max_page_editors() {
+    let filter = socksfinder::Filter {
+        max_page_editors: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&build_index(), &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        "Modèle:Infobox Equipe MotoGP/Bac à sable: 2/2 (Arkanosis, Arktest)\n",
+    );
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    assert_eq!(index.page_count(&filter).unwrap(), 1);
+    let matrix = index.cooccurrences(&["Arkanosis".to_string(), "Arktest".to_string(), "Arkbot".to_string()], &filter).unwrap();
+    assert_eq!((matrix.page_count(0), matrix.page_count(1), matrix.page_count(2)), (1, 1, 0));
+    assert_eq!((matrix.count(0, 1), matrix.count(0, 2)), (1, 0));
+}
+
+#[test]
+fn query_expressions() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let titles = |expression: &str| {
+        let expression = expression.parse::<socksfinder::Expression>().unwrap();
+        let mut pages = index.pages_matching(&expression, &socksfinder::Filter::default()).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        socksfinder::sort_pages(&mut pages, socksfinder::Order::alphabetical);
+        pages.into_iter().map(|page| page.title).collect::<Vec<_>>()
+    };
+    assert_eq!(titles("Arkanosis & Arktest & !Arkbot"), ["Modèle:Infobox Equipe MotoGP/Bac à sable"]);
+    assert_eq!(titles("(Arkanosis | 192.0.2.1) & Arkbot"), ["Aholfing", "Projet:Articles sans portail/1", "Utilisateur:Arktest/test"]);
+    assert_eq!(titles("2 of (Arkanosis, Arktest, Arkbot) & !(Arkanosis & Arkbot)"), ["Aholfing", "Modèle:Infobox Equipe MotoGP/Bac à sable"]);
+    assert_eq!(titles("\"Arkbot\" & !Arkanosis"), ["Aholfing"]);
+    assert_eq!(
+        "2 of (A_B, C) | !(D & E)".parse::<socksfinder::Expression>().err().map(|error| error.to_string()),
+        Some("expression '2 of (A_B, C) | !(D & E)' matches pages modified by none of its users".to_string()),
+    );
+    for invalid_expression in ["", "A &", "(A | B", "A, B", "0 of (A)", "\"A"] {
+        assert!(matches!(invalid_expression.parse::<socksfinder::Expression>(), Err(socksfinder::Error::InvalidQuery(_))));
+    }
+    assert_eq!(
+        "2 of (A_B, C) & !D".parse::<socksfinder::Expression>().unwrap().users(),
+        ["A B", "C", "D"],
+    );
+}
+
+#[test]
+fn page_editors() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    assert_eq!(index.page_editors("Aholfing").unwrap().unwrap(), ["192.0.2.1", "Arkbot", "Arktest"]);
+    assert_eq!(index.page_editors("Modèle:Infobox_Equipe_MotoGP/Bac_à_sable").unwrap().unwrap(), ["Arkanosis", "Arktest"]);
+    assert_eq!(index.page_editors("Nothing").unwrap(), None);
+    let titles = vec!["Aholfing".to_string(), "Utilisateur:Arktest/test".to_string(), "Nothing".to_string()];
+    let mut editors = index.editors(&titles, 2).unwrap();
+    editors.sort(socksfinder::Order::alphabetical);
+    assert_eq!(editors.unknown_pages(), ["Nothing"]);
+    assert_eq!(
+        editors.editors().iter().map(|editor| (editor.user.as_str(), editor.page_count)).collect::<Vec<_>>(),
+        [("Arkbot", 2), ("Arktest", 2)],
+    );
+    assert!(index.editors(&titles, 0).unwrap().editors().is_empty());
+    let mut output = vec![];
+    assert!(matches!(
+        socksfinder::page(&index, &mut output, &titles, 1, socksfinder::Order::count_decreasing, socksfinder::Format::text),
+        Err(socksfinder::Error::UnknownPages(_)),
+    ));
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Arkbot: 2 (Aholfing, Utilisateur:Arktest/test)\n\
+         Arktest: 2 (Aholfing, Utilisateur:Arktest/test)\n\
+         192.0.2.1: 1 (Aholfing)\n\
+         Arkanosis: 1 (Utilisateur:Arktest/test)\n",
+    );
+}
+
+#[test]
+fn similar_users() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let filter = socksfinder::Filter::default();
+    let similar_users = index.similar_users("Arkanosis", &filter, &[], socksfinder::Metric::jaccard).unwrap().unwrap();
+    assert_eq!(
+        similar_users.iter().map(|user| (user.user.as_str(), user.count, user.page_count)).collect::<Vec<_>>(),
+        [("Arktest", 3, 4), ("Arkbot", 2, 3)],
+    );
+    assert_eq!(similar_users[0].similarity, 0.75);
+    assert!(index.similar_users("Nobody", &filter, &[], socksfinder::Metric::count).unwrap().is_none());
+    let filter = socksfinder::Filter {
+        max_page_editors: Some(2),
+        ..Default::default()
+    };
+    let mut output = vec![];
+    socksfinder::similar(&index, &mut output, "Arkanosis", &filter, &["Arktest".to_string()], socksfinder::Metric::count, 10, false, socksfinder::Format::text).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "");
+    let mut output = vec![];
+    socksfinder::similar(&index, &mut output, "Arkbot", &socksfinder::Filter::default(), &[], socksfinder::Metric::count, 1, false, socksfinder::Format::csv).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "user,count,page_count\nArktest,3,4\n");
+    // the editors of a page are those of its record, even if another page has the same title
+    let dump = br#"<mediawiki>
+  <page><title>Foo</title><ns>0</ns>
+    <revision><timestamp>2020-05-06T12:00:00Z</timestamp><contributor><username>First user</username></contributor></revision>
+    <revision><timestamp>2020-05-06T13:00:00Z</timestamp><contributor><username>Second user</username></contributor></revision>
+  </page>
+  <page><title>Foo</title><ns>1</ns>
+    <revision><timestamp>2020-05-06T12:00:00Z</timestamp><contributor><username>Third user</username></contributor></revision>
+    <revision><timestamp>2020-05-06T13:00:00Z</timestamp><contributor><username>Fourth user</username></contributor></revision>
+  </page>
+</mediawiki>"#;
+    let mut index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(&dump[..])], &mut index, &build_options()).unwrap();
+    let index = socksfinder::IndexReader::from_bytes(index).unwrap();
+    let similar_users = index.similar_users("Third user", &socksfinder::Filter::default(), &[], socksfinder::Metric::count).unwrap().unwrap();
+    assert_eq!(similar_users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["Fourth user"]);
+}
+
+#[test]
+fn expand_seeds() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let seeds = vec!["Arkanosis".to_string(), "Nobody".to_string()];
+    let expansion = index.expand(&seeds, &socksfinder::Filter::default(), &[], 2, 0.5, 1).unwrap();
+    assert_eq!(expansion.unknown_users(), ["Nobody"]);
+    assert_eq!(
+        expansion.users().iter().map(|user| (user.user.as_str(), user.hop, user.seed_count, user.page_count, user.seed_score, user.page_score)).collect::<Vec<_>>(),
+        [("Arktest", 1, 1, 3, 1., 3.), ("Arkbot", 2, 2, 3, 1.5, 2.5)],
+    );
+    let mut output = vec![];
+    let seeds = vec!["Arkanosis".to_string(), "Arktest".to_string()];
+    socksfinder::expand(&index, &mut output, &seeds, &socksfinder::Filter::default(), &["192.0.2.1".to_string()], 1, 0.5, 0, socksfinder::Format::text).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Arkbot: 2 seeds, 3 pages\n");
+    assert!(matches!(
+        socksfinder::expand(&index, &mut vec![], &seeds, &socksfinder::Filter::default(), &[], 0, 0.5, 0, socksfinder::Format::text),
+        Err(socksfinder::Error::InvalidQuery(_)),
+    ));
+}
+
+#[test]
+fn similar_candidates() {
+    assert!(matches!(
+        socksfinder::IndexReader::from_bytes(build_index()).unwrap().similar_candidates("Arkanosis", &[], 0),
+        Err(socksfinder::Error::OutdatedIndex(_)),
+    ));
+    let mut index = vec![];
+    let options = socksfinder::BuildOptions {
+        signatures: true,
+        ..build_options()
+    };
+    socksfinder::build(&mut [&mut Cursor::new(DUMP)], &mut index, &options).unwrap();
+    socksfinder::verify(&mut Cursor::new(&index), &mut vec![]).unwrap();
+    let reader = socksfinder::IndexReader::from_bytes(index.clone()).unwrap();
+    let candidates = reader.similar_candidates("Arkanosis", &[], 0).unwrap().unwrap();
+    assert_eq!(candidates[0].user, "Arktest");
+    assert!((candidates[0].estimated_jaccard - 0.75).abs() < 0.2);
+    assert_eq!(reader.similar_candidates("Arkanosis", &[], 1).unwrap().unwrap(), candidates[..1]);
+    assert!(reader.similar_candidates("Arkanosis", &["Arktest".to_string()], 0).unwrap().unwrap().iter().all(|candidate| candidate.user != "Arktest"));
+    assert!(reader.similar_candidates("Nobody", &[], 0).unwrap().is_none());
+    let mut output = vec![];
+    socksfinder::similar(&reader, &mut output, "Arkanosis", &socksfinder::Filter::default(), &[], socksfinder::Metric::jaccard, 1, true, socksfinder::Format::csv).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!("user,count,page_count,jaccard,estimated_jaccard\nArktest,3,4,0.75,{}\n", candidates[0].estimated_jaccard),
+    );
+    // an update keeps the signatures, whose corruption is found by verify
+    let mut new_index = vec![];
+    socksfinder::update(&mut Cursor::new(&index), &mut Cursor::new(DUMP), &mut new_index, None).unwrap();
+    socksfinder::verify(&mut Cursor::new(&new_index), &mut vec![]).unwrap();
+    assert_eq!(socksfinder::IndexReader::from_bytes(new_index).unwrap().similar_candidates("Arkanosis", &[], 0).unwrap().unwrap(), candidates);
+    let corrupt_offset = section(&index, SIGNATURES_SECTION).0 + 20;
+    index[corrupt_offset] ^= 1;
+    assert!(socksfinder::verify(&mut Cursor::new(&index), &mut vec![]).is_err());
+}
+
+#[test]
+fn similar_candidates_skip_oversized_buckets() {
+    // Many users modify page 0 only, so they all share the same buckets, which
+    // are skipped; Seed and Twin also modify page 1, which sets them apart
+    let users: Vec<_> = (0..1001).map(|user| format!("User {}", user)).collect();
+    let mut user_pages: Vec<_> = users.iter().map(|user| (user.as_str(), 0..1)).collect();
+    user_pages.extend([("Seed", 0..2), ("Twin", 0..2)]);
+    let options = socksfinder::BuildOptions {
+        signatures: true,
+        ..build_options()
+    };
+    let index = build_user_pages_index(2, &user_pages, |_| 0, &options);
+    let reader = socksfinder::IndexReader::from_bytes(index).unwrap();
+    assert!(reader.similar_candidates("User 0", &[], 0).unwrap().unwrap().is_empty());
+    let candidates = reader.similar_candidates("Seed", &[], 0).unwrap().unwrap();
+    assert_eq!(candidates.iter().map(|candidate| candidate.user.as_str()).collect::<Vec<_>>(), ["Twin"]);
+}
+
+#[test]
+fn update_index() {
+    // Build from the first two pages, then update with the second page again and the last two
+    let dump = std::str::from_utf8(DUMP).unwrap();
+    let page_starts: Vec<_> = dump.match_indices("  <page>").map(|(offset, _)| offset).collect();
+    let old_dump = format!("{}</mediawiki>\n", &dump[..page_starts[2]]);
+    let new_dump = format!("{}{}", &dump[..page_starts[0]], &dump[page_starts[1]..]);
+    let mut old_index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(old_dump)], &mut old_index, &socksfinder::BuildOptions::default()).unwrap();
+    assert_eq!(
+        query(&old_index, &["Arkanosis", "Arktest", "Arkbot"], 3, socksfinder::Order::none),
+        "Utilisateur:Arktest/test: 3/3 (Arkanosis, Arktest, Arkbot)\n",
+    );
+    let mut new_index = vec![];
+    assert!(!socksfinder::update(&mut Cursor::new(&old_index), &mut Cursor::new(new_dump.as_bytes()), &mut new_index, None).unwrap().dropped_adjacency);
+    let metadata = socksfinder::metadata(&mut Cursor::new(&new_index)).unwrap().unwrap();
+    assert_eq!((metadata.page_count, metadata.user_count), (4, 4));
+    // only the time of the update, in the metadata, differs with a memory budget
+    let metadata_offset = section(&new_index, METADATA_SECTION).0;
+    for memory_budget in [0, 100, 1000] {
+        let mut external_index = vec![];
+        socksfinder::update(&mut Cursor::new(&old_index), &mut Cursor::new(new_dump.as_bytes()), &mut external_index, Some(memory_budget)).unwrap();
+        assert!(new_index[..metadata_offset] == external_index[..metadata_offset], "index updated with a memory budget of {} B differs", memory_budget);
+    }
+    assert_eq!(metadata.dump_time, socksfinder::parse_date("2020-05-07T05:05:05Z", false).unwrap());
+    let index = build_index();
+    for users in [&["Arkanosis", "Arktest", "Arkbot"][..], &["Arkanosis", "Arktest"], &["192.0.2.1", "Arkbot"]] {
+        assert_eq!(
+            query(&new_index, users, 0, socksfinder::Order::alphabetical),
+            query(&index, users, 0, socksfinder::Order::alphabetical),
+        );
+    }
+    let reader = socksfinder::IndexReader::from_bytes(new_index.clone()).unwrap();
+    let built_reader = socksfinder::IndexReader::from_bytes(index.clone()).unwrap();
+    for title in ["Utilisateur:Arktest/test", "Projet:Articles sans portail/1", "Modèle:Infobox Equipe MotoGP/Bac à sable", "Aholfing"] {
+        assert_eq!(reader.page_editors(title).unwrap(), built_reader.page_editors(title).unwrap());
+    }
+    socksfinder::verify(&mut Cursor::new(&new_index), &mut vec![]).unwrap();
+    let filter = socksfinder::Filter {
+        since: socksfinder::parse_date("2016-01-01", false),
+        namespaces: vec!["Projet".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        query_with_filter(&new_index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+        query_with_filter(&index, &["Arkanosis", "Arktest", "Arkbot"], 2, socksfinder::Order::alphabetical, &filter),
+    );
+}
+
+#[test]
+fn build_from_compressed_and_split_dumps() {
+    let index = build_index();
+    let mut gzip_encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    gzip_encoder.write_all(DUMP).unwrap();
+    let mut bzip2_encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+    bzip2_encoder.write_all(DUMP).unwrap();
+    let compressed_dumps = [
+        gzip_encoder.finish().unwrap(),
+        bzip2_encoder.finish().unwrap(),
+        zstd::encode_all(DUMP, 0).unwrap(),
+    ];
+    for compressed_dump in compressed_dumps {
+        let mut dump = socksfinder::open_dump(Box::new(Cursor::new(compressed_dump))).unwrap();
+        let mut compressed_index = vec![];
+        socksfinder::build(&mut [&mut dump], &mut compressed_index, &build_options()).unwrap();
+        assert!(index == compressed_index);
+    }
+    let dump = std::str::from_utf8(DUMP).unwrap();
+    let page_starts: Vec<_> = dump.match_indices("  <page>").map(|(offset, _)| offset).collect();
+    let first_part = format!("{}</mediawiki>\n", &dump[..page_starts[2]]);
+    let second_part = format!("{}{}", &dump[..page_starts[0]], &dump[page_starts[2]..]);
+    let mut split_index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(first_part), &mut Cursor::new(second_part)], &mut split_index, &build_options()).unwrap();
+    assert!(index == split_index);
+}
+
+#[test]
+fn verify_index() {
+    let index = build_index();
+    let mut output = vec![];
+    socksfinder::verify(&mut Cursor::new(&index), &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "No problem found in index in format version 5 (4 pages, 4 users)\n");
+    let mut output = vec![];
+    assert!(socksfinder::verify(&mut Cursor::new(&index[..index.len() - 100]), &mut output).is_err());
+    // Make the first posting of the first user point one byte after the start of its page
+    let mut corrupt_index = index.clone();
+    let (fst_offset, fst_length) = section(&index, FST_SECTION);
+    let fst = fst::Map::new(index[fst_offset..fst_offset + fst_length].to_vec()).unwrap();
+    let postings_offset = fst.get("192.0.2.1").unwrap() as usize;
+    corrupt_index[postings_offset + 8] += 1;
+    let mut output = vec![];
+    assert!(socksfinder::verify(&mut Cursor::new(&corrupt_index), &mut output).is_err());
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Error: posting 0 of user 192.0.2.1 points at offset 5, which isn't the start of a page name\n\
+         1 problem(s) found in index in format version 5\n",
+    );
+}
+
+#[test]
+fn index_metadata() {
+    let index = build_index();
+    assert_eq!(
+        socksfinder::metadata(&mut Cursor::new(&index)).unwrap(),
+        Some(socksfinder::Metadata {
+            dbname: "frwiki".to_string(),
+            base_url: "https://fr.wikipedia.org/wiki/Wikip%C3%A9dia:Accueil_principal".to_string(),
+            dump_time: socksfinder::parse_date("2020-05-07T05:05:05Z", false).unwrap(),
+            build_time: 1679270400,
+            socksfinder_version: socksfinder::version().to_string(),
+            page_count: 4,
+            user_count: 4,
+            revision_count: 13,
+        }),
+    );
+    assert_eq!(socksfinder::format_timestamp(1679270400), "2023-03-20T00:00:00Z");
+    assert_eq!(socksfinder::format_timestamp(951825599), "2000-02-29T11:59:59Z");
+}
+
+#[test]
+fn query_mapped_index() {
+    let index = build_index();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&index).unwrap();
+    let mapped_index = socksfinder::IndexReader::open(file.path()).unwrap();
+    assert_eq!(mapped_index.metadata().unwrap().page_count, 4);
+    let users = vec!["Arkanosis".to_string(), "Arktest".to_string()];
+    let mut mapped_output = vec![];
+    socksfinder::query(&mapped_index, &mut mapped_output, &users, 2, None, socksfinder::Order::alphabetical, &socksfinder::Filter::default(), false, socksfinder::Metric::count, None, 1, socksfinder::Format::text, false).unwrap();
+    let loaded_index = socksfinder::IndexReader::from_bytes(index.clone()).unwrap();
+    let mut loaded_output = vec![];
+    socksfinder::query(&loaded_index, &mut loaded_output, &users, 2, None, socksfinder::Order::alphabetical, &socksfinder::Filter::default(), false, socksfinder::Metric::count, None, 1, socksfinder::Format::text, false).unwrap();
+    assert_eq!(String::from_utf8(mapped_output).unwrap().lines().count(), 3);
+    assert_eq!(String::from_utf8(loaded_output).unwrap().lines().count(), 3);
+    assert!(socksfinder::IndexReader::from_bytes(index[..index.len() - 1].to_vec()).is_err());
+}
+
+#[test]
+fn reader_typed_results() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let users = vec!["Arktest".to_string(), "Arkanosis".to_string(), "Nobody".to_string(), "Arktest".to_string()];
+    let pages = index.pages(&users, 2, &socksfinder::Filter::default()).unwrap();
+    assert_eq!(pages.unknown_users(), ["Nobody"]);
+    let mut pages = pages.collect::<Result<Vec<_>, _>>().unwrap();
+    socksfinder::sort_pages(&mut pages, socksfinder::Order::alphabetical);
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0], socksfinder::PageMatch {
+        title: "Modèle:Infobox Equipe MotoGP/Bac à sable".to_string(),
+        namespace: 10,
+        editors: vec!["Arktest".to_string(), "Arkanosis".to_string()],
+        editor_count: 2,
+        total_editor_count: Some(2),
+        rarity: 2. * 2_f64.ln(),
+    });
+    let users = vec!["Arkanosis".to_string(), "Arktest".to_string(), "Arkbot".to_string()];
+    let mut matrix = index.cooccurrences(&users, &socksfinder::Filter::default()).unwrap();
+    assert_eq!((matrix.count(0, 1), matrix.count(0, 2), matrix.count(1, 2), matrix.count(1, 1)), (3, 2, 3, 0));
+    matrix.sort(socksfinder::Order::count_increasing, socksfinder::Metric::count);
+    assert_eq!(matrix.users(), ["Arkanosis", "Arkbot", "Arktest"]);
+    assert_eq!((matrix.total(0), matrix.total(1), matrix.total(2)), (5, 5, 6));
+    let filter = socksfinder::Filter {
+        namespaces: vec!["Utilisateur".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        index.user_pages("Arkbot", &filter).unwrap(),
+        Some(vec![socksfinder::UserPage {
+            title: "Utilisateur:Arktest/test".to_string(),
+            namespace: 2,
+            first_edit: socksfinder::parse_date("2018-09-10T18:00:00Z", false),
+            last_edit: socksfinder::parse_date("2018-09-10T18:00:00Z", false),
+        }]),
+    );
+    assert_eq!(index.user_pages("Nobody", &filter).unwrap(), None);
+}
+
+#[test]
+fn cooccurrence_metrics() {
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let users = vec!["Arkanosis".to_string(), "Arktest".to_string(), "Arkbot".to_string()];
+    let mut matrix = index.cooccurrences(&users, &socksfinder::Filter::default()).unwrap();
+    assert_eq!((matrix.page_count(0), matrix.page_count(1), matrix.page_count(2)), (3, 4, 3));
+    assert_eq!((matrix.similarity(0, 1, socksfinder::Metric::jaccard), matrix.similarity(0, 2, socksfinder::Metric::jaccard), matrix.similarity(1, 1, socksfinder::Metric::jaccard)), (0.75, 0.5, 0.));
+    assert_eq!(matrix.similarity(0, 1, socksfinder::Metric::overlap), 1.);
+    assert_eq!(matrix.similarity(0, 1, socksfinder::Metric::cosine), 3. / 12_f64.sqrt());
+    assert_eq!(matrix.similarity(0, 1, socksfinder::Metric::dice), 6. / 7.);
+    matrix.sort(socksfinder::Order::count_decreasing, socksfinder::Metric::jaccard);
+    assert_eq!(matrix.users(), ["Arktest", "Arkanosis", "Arkbot"]);
+    assert_eq!((matrix.page_count(0), matrix.similarity(0, 1, socksfinder::Metric::jaccard)), (4, 0.75));
+    let filter = socksfinder::Filter {
+        namespaces: vec!["0".to_string()],
+        ..Default::default()
+    };
+    let matrix = index.cooccurrences(&users, &filter).unwrap();
+    assert_eq!((matrix.page_count(0), matrix.page_count(1), matrix.page_count(2)), (0, 1, 1));
+    assert_eq!((matrix.similarity(0, 1, socksfinder::Metric::jaccard), matrix.similarity(1, 2, socksfinder::Metric::jaccard)), (0., 1.));
+}
+
+#[test]
+fn typed_errors() {
+    let index = build_index();
+    let mut not_an_index = index.clone();
+    not_an_index[0] = b'X';
+    assert!(matches!(socksfinder::IndexReader::from_bytes(not_an_index), Err(socksfinder::Error::BadMagic)));
+    let mut future_index = index.clone();
+    future_index[2] = 42;
+    assert!(matches!(socksfinder::IndexReader::from_bytes(future_index), Err(socksfinder::Error::UnsupportedVersion(42))));
+    assert!(matches!(socksfinder::IndexReader::from_bytes(index[..index.len() - 1].to_vec()), Err(socksfinder::Error::Corrupt(_))));
+    let reader = socksfinder::IndexReader::from_bytes(index).unwrap();
+    let users = vec!["Arkanosis".to_string(), "Nobody".to_string(), "Arktest".to_string()];
+    let mut output = vec![];
+    match socksfinder::query(&reader, &mut output, &users, 2, None, socksfinder::Order::alphabetical, &socksfinder::Filter::default(), false, socksfinder::Metric::count, None, 1, socksfinder::Format::text, false) {
+        Err(socksfinder::Error::UnknownUsers(unknown_users)) => assert_eq!(unknown_users, ["Nobody"]),
+        _ => panic!("unknown user not reported"),
+    }
+    // pages of the known users are still written
+    assert_eq!(String::from_utf8(output).unwrap().lines().count(), 3);
+    let filter = socksfinder::Filter {
+        namespaces: vec!["Foo".to_string()],
+        ..Default::default()
+    };
+    match reader.pages(&users, 0, &filter) {
+        Err(error @ socksfinder::Error::InvalidQuery(_)) => assert_eq!(error.to_string(), "namespace 'Foo' does not exist"),
+        _ => panic!("unknown namespace not reported"),
+    }
+    let mut broken_dump = Cursor::new(&b"<mediawiki><page><title>Foo</title></mediawiki>"[..]);
+    assert!(matches!(socksfinder::build(&mut [&mut broken_dump], &mut vec![], &build_options()), Err(socksfinder::Error::Xml { .. })));
+}
+
+#[test]
+fn cooccurrence_significance() {
+    // First user modifies pages 0 to 4, second user pages 0 to 3 and 10 to
+    // 13, third user page 19, and pages 15 to 19 are in namespace 2
+    let user_pages = [("First", 0..5), ("Second", 0..4), ("Second", 10..14), ("Third", 19..20)];
+    let index = build_user_pages_index(20, &user_pages, |page| if page < 15 { 0 } else { 2 }, &build_options());
+    let index = socksfinder::IndexReader::from_bytes(index).unwrap();
+    let users = vec!["Third".to_string(), "First".to_string(), "Second".to_string()];
+    let mut matrix = index.cooccurrences(&users, &socksfinder::Filter::default()).unwrap();
+    assert!((matrix.p_value(1, 2) - 0.05779153766769866).abs() < 1e-9);
+    assert_eq!((matrix.p_value(0, 1), matrix.p_value(1, 1)), (1., 1.));
+    matrix.sort(socksfinder::Order::count_decreasing, socksfinder::Metric::significance);
+    assert_eq!(matrix.users(), ["First", "Second", "Third"]);
+    matrix.retain_significant(0.1);
+    assert_eq!(matrix.users(), ["First", "Second"]);
+    let filter = socksfinder::Filter {
+        namespaces: vec!["0".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(index.page_count(&filter).unwrap(), 15);
+    let mut matrix = index.cooccurrences(&users, &filter).unwrap();
+    assert!((matrix.p_value(1, 2) - 2. / 11.).abs() < 1e-9);
+    matrix.retain_significant(0.1);
+    assert!(matrix.users().is_empty());
+}
+
+#[test]
+fn cooccurrence_communities() {
+    // A, B and C modify pages 0 to 2, D and E pages 3 to 5, and C and D both
+    // modify page 6
+    let user_pages = [("A", 0..3), ("B", 0..3), ("C", 0..3), ("C", 6..7), ("D", 3..7), ("E", 3..6)];
+    let index = build_user_pages_index(7, &user_pages, |_| 0, &build_options());
+    let index = socksfinder::IndexReader::from_bytes(index).unwrap();
+    let users: Vec<String> = ["E", "A", "D", "B", "C", "F"].iter().map(|user| user.to_string()).collect();
+    let matrix = index.cooccurrences(&users, &socksfinder::Filter::default()).unwrap();
+    let communities = matrix.communities(socksfinder::Metric::count);
+    assert_eq!(communities.unknown_users(), ["F"]);
+    assert_eq!(communities.communities().iter().map(|community| (community.users.clone(), community.density)).collect::<Vec<_>>(), [
+        (vec!["A".to_string(), "B".to_string(), "C".to_string()], 1.),
+        (vec!["E".to_string(), "D".to_string()], 1.),
+    ]);
+    let bridges: Vec<_> = communities.bridges().iter().map(|bridge| (bridge.user.as_str(), bridge.community, bridge.linked_communities.clone())).collect();
+    assert_eq!(bridges, [("D", 2, vec![1]), ("C", 1, vec![2])]);
+    assert!((communities.bridges()[0].participation - 0.375).abs() < 1e-9);
+    assert!(communities.modularity() > 0.3);
+    let mut output = vec![];
+    assert!(matches!(socksfinder::communities(&index, &mut output, &users, &socksfinder::Filter::default(), socksfinder::Metric::jaccard, None, socksfinder::Format::text), Err(socksfinder::Error::UnknownUsers(_))));
+    assert!(String::from_utf8(output).unwrap().starts_with("Community 1: A, B, C (density 1.000)\nCommunity 2: E, D (density 1.000)\nBridges:\nD: community 2, linked to 1 ("));
+}
+
+#[test]
+fn cooccurrence_graphs() {
+    let index = build_index();
+    let reader = socksfinder::IndexReader::from_bytes(index.clone()).unwrap();
+    assert_eq!((reader.edit_count("Arktest").unwrap(), reader.edit_count("Nobody").unwrap()), (Some(5), None));
+    let users = vec!["Arkanosis".to_string(), "Arkbot".to_string(), "192.0.2.1".to_string()];
+    let mut output = vec![];
+    socksfinder::query(&reader, &mut output, &users, 0, None, socksfinder::Order::none, &socksfinder::Filter::default(), true, socksfinder::Metric::jaccard, None, 2, socksfinder::Format::dot, false).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "graph cooccurrences {\n  \"Arkanosis\" [edit_count=4, page_count=3];\n  \"Arkbot\" [edit_count=3, page_count=3];\n  \"192.0.2.1\" [edit_count=1, page_count=1];\n  \"Arkanosis\" -- \"Arkbot\" [weight=2, label=2, jaccard=0.5];\n}\n",
+    );
+    let mut output = vec![];
+    socksfinder::query(&reader, &mut output, &users, 0, None, socksfinder::Order::none, &socksfinder::Filter::default(), true, socksfinder::Metric::count, None, 1, socksfinder::Format::gexf, false).unwrap();
+    let gexf = String::from_utf8(output).unwrap();
+    assert!(gexf.contains("<node id=\"0\" label=\"Arkanosis\"><attvalues><attvalue for=\"edit_count\" value=\"4\"/><attvalue for=\"page_count\" value=\"3\"/></attvalues></node>"));
+    assert_eq!(gexf.matches("<edge ").count(), 2);
+    assert!(matches!(
+        socksfinder::query(&reader, &mut vec![], &users, 0, None, socksfinder::Order::none, &socksfinder::Filter::default(), false, socksfinder::Metric::count, None, 1, socksfinder::Format::graphml, false),
+        Err(socksfinder::Error::InvalidQuery(_)),
+    ));
+    // edit counts are added up by updates, and checked by verify
+    let mut new_index = vec![];
+    socksfinder::update(&mut Cursor::new(&index), &mut Cursor::new(DUMP), &mut new_index, None).unwrap();
+    socksfinder::verify(&mut Cursor::new(&new_index), &mut vec![]).unwrap();
+    assert_eq!(socksfinder::IndexReader::from_bytes(new_index).unwrap().edit_count("Arktest").unwrap(), Some(10));
+    let mut corrupt_index = index.clone();
+    corrupt_index[section(&index, EDIT_COUNTS_SECTION).0] = 0;
+    assert!(socksfinder::verify(&mut Cursor::new(&corrupt_index), &mut vec![]).is_err());
+}
+
+#[test]
+fn revision_adjacency() {
+    assert!(matches!(
+        socksfinder::IndexReader::from_bytes(build_index()).unwrap().follows("Arktest", "Arkanosis", &socksfinder::Filter::default()),
+        Err(socksfinder::Error::OutdatedIndex(_)),
+    ));
+    let mut index = vec![];
+    let options = socksfinder::BuildOptions {
+        adjacency: true,
+        ..build_options()
+    };
+    socksfinder::build(&mut [&mut Cursor::new(DUMP)], &mut index, &options).unwrap();
+    socksfinder::verify(&mut Cursor::new(&index), &mut vec![]).unwrap();
+    for memory_budget in [0, 100, 1000] {
+        let mut external_index = vec![];
+        let options = socksfinder::BuildOptions {
+            memory_budget: Some(memory_budget),
+            ..options
+        };
+        socksfinder::build(&mut [&mut Cursor::new(DUMP)], &mut external_index, &options).unwrap();
+        assert!(index == external_index, "index built with a memory budget of {} B differs", memory_budget);
+    }
+    let reader = socksfinder::IndexReader::from_bytes(index.clone()).unwrap();
+    // the delay is counted from the last of several revisions in a row
+    let follows = reader.follows("Arktest", "Arkanosis", &socksfinder::Filter::default()).unwrap();
+    assert_eq!(follows.pages().iter().map(|page| (page.title.as_str(), page.count, page.shortest_delay)).collect::<Vec<_>>(), [
+        ("Utilisateur:Arktest/test", 1, 300),
+        ("Projet:Articles sans portail/1", 1, 86400),
+        ("Modèle:Infobox Equipe MotoGP/Bac à sable", 1, 86400),
+    ]);
+    assert!(reader.follows("Arkanosis", "Arktest", &socksfinder::Filter::default()).unwrap().pages().is_empty());
+    assert_eq!(reader.follows("Arktest", "Nobody", &socksfinder::Filter::default()).unwrap().unknown_users(), ["Nobody"]);
+    let mut output = vec![];
+    socksfinder::follows(&reader, &mut output, "Arktest", "Arkbot", socksfinder::Order::none, &socksfinder::Filter::default(), socksfinder::Format::text).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Utilisateur:Arktest/test: 1 (shortest 00:02:00, average 00:02:00)\n");
+    let mut output = vec![];
+    let filter = socksfinder::Filter {
+        namespaces: vec!["0".to_string()],
+        ..Default::default()
+    };
+    socksfinder::follows(&reader, &mut output, "192.0.2.1", "Arkbot", socksfinder::Order::none, &filter, socksfinder::Format::csv).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "title,namespace,count,shortest_delay,average_delay\nAholfing,0,1,80321400,80321400\n");
+    // an update drops the adjacency, as the revisions the newer ones follow aren't known
+    let mut new_index = vec![];
+    assert!(socksfinder::update(&mut Cursor::new(&index), &mut Cursor::new(DUMP), &mut new_index, None).unwrap().dropped_adjacency);
+    socksfinder::verify(&mut Cursor::new(&new_index), &mut vec![]).unwrap();
+    assert!(socksfinder::IndexReader::from_bytes(new_index).unwrap().follows("Arktest", "Arkanosis", &socksfinder::Filter::default()).is_err());
+    // entries follow the position of the first entry of each of the 4 users
+    let corrupt_offset = section(&index, ADJACENCY_SECTION).0 + 8 * 5;
+    index[corrupt_offset] ^= 0x10;
+    assert!(socksfinder::verify(&mut Cursor::new(&index), &mut vec![]).is_err());
+}
+
+#[test]
+fn query_formats() {
+    let dump = br#"<mediawiki>
+  <page><title>Foo, "bar"</title><ns>0</ns>
+    <revision><timestamp>2020-05-06T12:00:00Z</timestamp><contributor><username>First user</username></contributor></revision>
+    <revision><timestamp>2020-05-06T13:00:00Z</timestamp><contributor><username>Second, user</username></contributor></revision>
+  </page>
+</mediawiki>"#;
+    let mut index = vec![];
+    socksfinder::build(&mut [&mut Cursor::new(&dump[..])], &mut index, &build_options()).unwrap();
+    let index = socksfinder::IndexReader::from_bytes(index).unwrap();
+    let users = vec!["First user".to_string(), "Second, user".to_string()];
+    let query = |show_cooccurrences: bool, format: socksfinder::Format| {
+        let mut output = vec![];
+        socksfinder::query(&index, &mut output, &users, 0, None, socksfinder::Order::none, &socksfinder::Filter::default(), show_cooccurrences, socksfinder::Metric::count, None, 1, format, false).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    assert_eq!(query(false, socksfinder::Format::text), "Foo, \"bar\": 2/2 (First user, Second, user)\n");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&query(false, socksfinder::Format::json)).unwrap(),
+        serde_json::json!([{
+            "title": "Foo, \"bar\"",
+            "namespace": 0,
+            "editors": ["First user", "Second, user"],
+            "editor_count": 2,
+            "total_editor_count": 2,
+        }]),
+    );
+    assert_eq!(
+        query(false, socksfinder::Format::jsonl),
+        "{\"title\":\"Foo, \\\"bar\\\"\",\"namespace\":0,\"editors\":[\"First user\",\"Second, user\"],\"editor_count\":2,\"total_editor_count\":2}\n",
+    );
+    assert_eq!(
+        query(false, socksfinder::Format::csv),
+        "title,namespace,editor_count,total_editor_count,editors\n\
+         \"Foo, \"\"bar\"\"\",0,2,2,\"First user|Second, user\"\n",
+    );
+    assert_eq!(
+        query(false, socksfinder::Format::tsv),
+        "title\tnamespace\teditor_count\ttotal_editor_count\teditors\n\
+         \"Foo, \"\"bar\"\"\"\t0\t2\t2\tFirst user|Second, user\n",
+    );
+    assert_eq!(
+        query(true, socksfinder::Format::json),
+        "{\"counts\":[[0,1],[1,0]],\"users\":[\"First user\",\"Second, user\"]}\n",
+    );
+    assert_eq!(
+        query(true, socksfinder::Format::jsonl),
+        "{\"count\":1,\"first_user\":\"First user\",\"second_user\":\"Second, user\"}\n",
+    );
+    assert_eq!(
+        query(true, socksfinder::Format::csv),
+        "user,First user,\"Second, user\"\n\
+         First user,,1\n\
+         \"Second, user\",1,\n",
+    );
+    // the dump has no namespaces, so links are in English
+    assert_eq!(
+        query(false, socksfinder::Format::wikitext),
+        "{| class=\"wikitable sortable\"\n\
+         ! Page !! Editor count !! Total editor count !! Editors\n\
+         |-\n\
+         | [[:Foo, \"bar\"]] || 2 || 2 || [[Special:Contributions/First user|First user]], [[Special:Contributions/Second, user|Second, user]]\n\
+         |}\n",
+    );
+    assert_eq!(
+        query(true, socksfinder::Format::wikitext),
+        "{| class=\"wikitable\"\n\
+         ! !! [[Special:Contributions/First user|First user]] !! [[Special:Contributions/Second, user|Second, user]]\n\
+         |-\n\
+         ! [[Special:Contributions/First user|First user]]\n\
+         |  || 1\n\
+         |-\n\
+         ! [[Special:Contributions/Second, user|Second, user]]\n\
+         | 1 || \n\
+         |}\n",
+    );
+    let index = socksfinder::IndexReader::from_bytes(build_index()).unwrap();
+    let mut output = vec![];
+    socksfinder::query(&index, &mut output, &["Arkanosis".to_string(), "Arkbot".to_string()], 0, None, socksfinder::Order::alphabetical, &socksfinder::Filter::default(), false, socksfinder::Metric::count, None, 1, socksfinder::Format::wikitext, false).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap().lines().nth(3),
+        Some("| [[:Projet:Articles sans portail/1]] || 2 || 3 || [[Spécial:Contributions/Arkanosis|Arkanosis]], [[Spécial:Contributions/Arkbot|Arkbot]]"),
+    );
+}